@@ -18,6 +18,8 @@ pub struct PerspectiveCamera {
     pub znear: f32,
     // z_far,
     pub zfar: f32,
+    // depth 1 at znear and 0 at zfar
+    pub reversed_z: bool,
     // projection matrix
     pub projection_matrix: Matrix<4,4>,
     
@@ -28,7 +30,8 @@ impl PerspectiveCamera {
         Self{
             znear: znear,
             zfar: zfar,
-            projection_matrix: calculate_prespective_projection(degrees_to_radians(fov), aspect, znear, zfar, 1f32),
+            reversed_z: false,
+            projection_matrix: calculate_prespective_projection(degrees_to_radians(fov), aspect, znear, zfar, 1f32, false),
             camera: Camera{
                 fov: fov,
                 aspect: aspect,
@@ -36,6 +39,16 @@ impl PerspectiveCamera {
             }
         }
     }
+
+    pub fn with_reversed_z(mut self, reversed_z: bool) -> Self {
+        self.set_reversed_z(reversed_z);
+        self
+    }
+
+    pub fn set_reversed_z(&mut self, reversed_z: bool) {
+        self.reversed_z = reversed_z;
+        self.update_projection();
+    }
 }
 
 
@@ -46,7 +59,8 @@ impl Default for PerspectiveCamera {
             camera: Default::default(), 
             znear: 0.01f32,
             zfar: 1000f32,
-            projection_matrix: calculate_prespective_projection(degrees_to_radians(50f32), 1f32, 0.01f32, 1000f32, 1f32),
+            reversed_z: false,
+            projection_matrix: calculate_prespective_projection(degrees_to_radians(50f32), 1f32, 0.01f32, 1000f32, 1f32, false),
         }
     }
 }
@@ -141,7 +155,11 @@ impl Projectable for PerspectiveCamera {
         &self.projection_matrix
     }
 
+    fn reversed_z(&self) -> bool {
+        self.reversed_z
+    }
+
     fn update_projection(&mut self) {
-        self.projection_matrix = calculate_prespective_projection(self.fov_in_radians(), self.aspect(), self.znear,self.zfar, self.zoom());
+        self.projection_matrix = calculate_prespective_projection(self.fov_in_radians(), self.aspect(), self.znear,self.zfar, self.zoom(), self.reversed_z);
     }
 }
\ No newline at end of file
@@ -32,4 +32,6 @@ pub trait CameraTrait {
 pub trait Projectable: CameraTrait {
     fn get_projection(&self) -> &Matrix<4,4>;
     fn update_projection(&mut self);
+    // whether the projection maps the near plane to depth 1 and the far plane to 0
+    fn reversed_z(&self) -> bool;
 }
@@ -0,0 +1,433 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read, Write};
+
+use crate::la::Vec3f;
+
+// why a high dynamic range image could not be read
+#[derive(Clone, Debug, PartialEq)]
+pub enum HdrError {
+    // the data ends before the header or the pixels
+    Truncated,
+    // neither a radiance nor a pfm file
+    UnknownFormat,
+    // a radiance pixel format other than 32-bit_rle_rgbe
+    UnsupportedFormat(String),
+    // a radiance resolution line other than -Y height +X width
+    UnsupportedOrientation(String),
+    // a width or height that can not be parsed or is too big for an image
+    BadSize,
+    BadScale,
+    // a run length encoded scanline that does not match the width
+    BadScanline,
+    Io(String),
+}
+
+impl fmt::Display for HdrError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HdrError::Truncated => write!(f, "the hdr data is truncated"),
+            HdrError::UnknownFormat => write!(f, "not a radiance hdr or pfm file"),
+            HdrError::UnsupportedFormat(s) => write!(f, "unsupported hdr format {}", s),
+            HdrError::UnsupportedOrientation(s) => write!(f, "unsupported hdr orientation {}", s),
+            HdrError::BadSize => write!(f, "bad hdr image size"),
+            HdrError::BadScale => write!(f, "bad pfm scale"),
+            HdrError::BadScanline => write!(f, "bad hdr scanline data"),
+            HdrError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+// high dynamic range image, the pixels are linear radiance and row 0 is the top of the image
+pub struct HdrImage {
+    pub width: i32,
+    pub height: i32,
+    pub data: Vec<Vec3f>,
+}
+
+impl HdrImage {
+    pub fn new(width: i32, height: i32) -> HdrImage {
+        HdrImage {
+            width,
+            height,
+            data: vec![Vec3f::zeroed(); (width * height) as usize],
+        }
+    }
+
+    pub fn pixel_at(&self, x: i32, y: i32) -> Vec3f {
+        *self.data
+            .get((x + y * self.width) as usize)
+            .unwrap_or(&Vec3f::zeroed())
+    }
+
+    pub fn set_pixel(&mut self, x: i32, y: i32, c: Vec3f) {
+        self.data[(x + y * self.width) as usize] = c;
+    }
+
+    // the root mean square difference of the channels of two images of the same size
+    pub fn rmse(&self, other: &HdrImage) -> Option<f32> {
+        if (self.width, self.height) != (other.width, other.height) {
+            return None;
+        }
+        let sum: f32 = self
+            .data
+            .iter()
+            .zip(other.data.iter())
+            .map(|(a, b)| {
+                let d = *a - *b;
+                d.dot(&d)
+            })
+            .sum();
+        Some((sum / (3 * self.data.len()).max(1) as f32).sqrt())
+    }
+
+    // read a radiance .hdr or a .pfm file
+    pub fn from_raw_vec(v: Vec<u8>) -> Result<Self, HdrError> {
+        if v.starts_with(b"PF") || v.starts_with(b"Pf") {
+            HdrImage::from_pfm(&v)
+        } else {
+            HdrImage::from_radiance(&v)
+        }
+    }
+
+    // read a radiance rgbe (.hdr) file, flat or run length encoded
+    pub fn from_radiance(v: &[u8]) -> Result<Self, HdrError> {
+        let mut pos = 0;
+        let next_line = |pos: &mut usize| -> Result<String, HdrError> {
+            let start = *pos;
+            while *pos < v.len() && v[*pos] != b'\n' {
+                *pos += 1;
+            }
+            if *pos >= v.len() {
+                return Err(HdrError::Truncated);
+            }
+            *pos += 1;
+            Ok(String::from_utf8_lossy(&v[start..*pos - 1]).trim().to_owned())
+        };
+
+        let magic = next_line(&mut pos)?;
+        if !magic.starts_with("#?") {
+            return Err(HdrError::UnknownFormat);
+        }
+        // the header ends with an empty line
+        loop {
+            let line = next_line(&mut pos)?;
+            if line.is_empty() {
+                break;
+            }
+            if line.starts_with("FORMAT=") && line != "FORMAT=32-bit_rle_rgbe" {
+                return Err(HdrError::UnsupportedFormat(line["FORMAT=".len()..].to_owned()));
+            }
+        }
+        // only the standard orientation is supported: top to bottom, left to right
+        let resolution = next_line(&mut pos)?;
+        let items: Vec<&str> = resolution.split_ascii_whitespace().collect();
+        let (height, width) = match items[..] {
+            ["-Y", h, "+X", w] => (
+                h.parse::<i32>().map_err(|_| HdrError::BadSize)?,
+                w.parse::<i32>().map_err(|_| HdrError::BadSize)?,
+            ),
+            _ => return Err(HdrError::UnsupportedOrientation(resolution)),
+        };
+        if width <= 0 || height <= 0 {
+            return Err(HdrError::BadSize);
+        }
+        // the smallest a scanline can be, a run of 127 pixels per 2 bytes and channel when encoded,
+        // so that a bad size is rejected before allocating the image
+        let (w, h) = (width as usize, height as usize);
+        let scanline_bytes = if (8..32768).contains(&w) { 4 + 4 * 2 * w.div_ceil(127) } else { 4 * w };
+        let min_bytes = h.checked_mul(scanline_bytes).ok_or(HdrError::BadSize)?;
+        // HdrImage::new multiplies the sizes as i32
+        if w.checked_mul(h).is_none_or(|count| count > i32::MAX as usize) {
+            return Err(HdrError::BadSize);
+        }
+        if min_bytes > v.len() - pos {
+            return Err(HdrError::Truncated);
+        }
+
+        let mut image = HdrImage::new(width, height);
+        let mut scanline = vec![[0u8; 4]; width as usize];
+        for y in 0..height {
+            read_scanline(v, &mut pos, &mut scanline)?;
+            for (x, rgbe) in scanline.iter().enumerate() {
+                image.set_pixel(x as i32, y, rgbe_to_vec(rgbe));
+            }
+        }
+        Ok(image)
+    }
+
+    // read a portable float map, color (PF) or grayscale (Pf), of either endianness
+    pub fn from_pfm(v: &[u8]) -> Result<Self, HdrError> {
+        let channels = match v.get(..2) {
+            Some(b"PF") => 3,
+            Some(b"Pf") => 1,
+            _ => return Err(HdrError::UnknownFormat),
+        };
+        // the width, height and scale are separated by whitespace, a single one before the data
+        let mut pos = 2;
+        let mut token = || -> Result<String, HdrError> {
+            while v.get(pos).is_some_and(|b| b.is_ascii_whitespace()) {
+                pos += 1;
+            }
+            let start = pos;
+            while v.get(pos).is_some_and(|b| !b.is_ascii_whitespace()) {
+                pos += 1;
+            }
+            if start == pos {
+                return Err(HdrError::Truncated);
+            }
+            Ok(String::from_utf8_lossy(&v[start..pos]).into_owned())
+        };
+        let width: usize = token()?.parse().map_err(|_| HdrError::BadSize)?;
+        let height: usize = token()?.parse().map_err(|_| HdrError::BadSize)?;
+        let scale: f32 = token()?.parse().map_err(|_| HdrError::BadScale)?;
+        pos += 1;
+        let count = width.checked_mul(height).filter(|&c| c <= i32::MAX as usize).ok_or(HdrError::BadSize)?;
+        let end = count
+            .checked_mul(channels * 4)
+            .and_then(|bytes| bytes.checked_add(pos))
+            .ok_or(HdrError::BadSize)?;
+        let (width, height) = (width as i32, height as i32);
+
+        // a negative scale is little endian
+        let read = if scale < 0.0 { f32::from_le_bytes } else { f32::from_be_bytes };
+        let floats: Vec<f32> = v
+            .get(pos..end)
+            .ok_or(HdrError::Truncated)?
+            .chunks_exact(4)
+            .map(|b| read([b[0], b[1], b[2], b[3]]))
+            .collect();
+        let mut image = HdrImage::new(width, height);
+        for (i, p) in floats.chunks_exact(channels).enumerate() {
+            let (x, y) = (i as i32 % width, i as i32 / width);
+            let c = if channels == 3 { Vec3f(p[0], p[1], p[2]) } else { Vec3f(p[0], p[0], p[0]) };
+            // the rows are stored bottom to top
+            image.set_pixel(x, height - 1 - y, c);
+        }
+        Ok(image)
+    }
+
+    pub fn from_file(f: String) -> Result<Self, HdrError> {
+        let mut f = File::open(f).map_err(|e| HdrError::Io(e.to_string()))?;
+        let mut buf = Vec::new();
+        f.read_to_end(&mut buf).map_err(|e| HdrError::Io(e.to_string()))?;
+        HdrImage::from_raw_vec(buf)
+    }
+
+    // a radiance rgbe file, the scanlines are run length encoded when they can be
+    pub fn to_radiance(&self) -> Vec<u8> {
+        let mut v = format!("#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n", self.height, self.width).into_bytes();
+        for row in self.data.chunks(self.width.max(1) as usize) {
+            let rgbe: Vec<[u8; 4]> = row.iter().map(vec_to_rgbe).collect();
+            if !(8..32768).contains(&row.len()) {
+                v.extend(rgbe.iter().flatten());
+                continue;
+            }
+            v.extend_from_slice(&[2, 2, (row.len() >> 8) as u8, row.len() as u8]);
+            for channel in 0..4 {
+                let values: Vec<u8> = rgbe.iter().map(|p| p[channel]).collect();
+                write_rle(&mut v, &values);
+            }
+        }
+        v
+    }
+
+    // a little endian color portable float map
+    pub fn to_pfm(&self) -> Vec<u8> {
+        let mut v = format!("PF\n{} {}\n-1.0\n", self.width, self.height).into_bytes();
+        for row in self.data.chunks(self.width.max(1) as usize).rev() {
+            for c in row {
+                for f in [c.0, c.1, c.2] {
+                    v.extend_from_slice(&f.to_le_bytes());
+                }
+            }
+        }
+        v
+    }
+
+    pub fn write_to_radiance(&self, filename: &str) -> io::Result<()> {
+        File::create(filename)?.write_all(&self.to_radiance())
+    }
+
+    pub fn write_to_pfm(&self, filename: &str) -> io::Result<()> {
+        File::create(filename)?.write_all(&self.to_pfm())
+    }
+}
+
+fn rgbe_to_vec(rgbe: &[u8; 4]) -> Vec3f {
+    if rgbe[3] == 0 {
+        return Vec3f::zeroed();
+    }
+    let f = libm::ldexpf(1.0, rgbe[3] as i32 - (128 + 8));
+    Vec3f(rgbe[0] as f32 * f, rgbe[1] as f32 * f, rgbe[2] as f32 * f)
+}
+
+// the shared exponent is the one of the brightest channel
+fn vec_to_rgbe(c: &Vec3f) -> [u8; 4] {
+    let v = c.0.max(c.1).max(c.2);
+    if v < 1e-32 {
+        return [0, 0, 0, 0];
+    }
+    let (m, e) = libm::frexpf(v);
+    let f = m * 256.0 / v;
+    let channel = |c: f32| (c.max(0.0) * f) as u8;
+    [channel(c.0), channel(c.1), channel(c.2), (e + 128) as u8]
+}
+
+// one channel of a scanline: runs of 4 or more equal bytes, and raw bytes in between
+fn write_rle(v: &mut Vec<u8>, data: &[u8]) {
+    let run = |x: usize, max: usize| data[x..].iter().take(max).take_while(|&&b| b == data[x]).count();
+    let mut x = 0;
+    while x < data.len() {
+        let n = run(x, 127);
+        if n >= 4 {
+            v.extend_from_slice(&[128 + n as u8, data[x]]);
+            x += n;
+            continue;
+        }
+        let start = x;
+        while x < data.len() && x - start < 128 && run(x, 4) < 4 {
+            x += 1;
+        }
+        v.push((x - start) as u8);
+        v.extend_from_slice(&data[start..x]);
+    }
+}
+
+fn read_scanline(v: &[u8], pos: &mut usize, scanline: &mut [[u8; 4]]) -> Result<(), HdrError> {
+    let width = scanline.len();
+    let byte = |pos: &mut usize| -> Result<u8, HdrError> {
+        let b = *v.get(*pos).ok_or(HdrError::Truncated)?;
+        *pos += 1;
+        Ok(b)
+    };
+
+    let rle = (8..32768).contains(&width)
+        && v.get(*pos) == Some(&2)
+        && v.get(*pos + 1) == Some(&2)
+        && v.get(*pos + 2).is_some_and(|b| b & 0x80 == 0);
+    if !rle {
+        // flat scanline
+        for pixel in scanline.iter_mut() {
+            for c in pixel.iter_mut() {
+                *c = byte(pos)?;
+            }
+        }
+        return Ok(());
+    }
+
+    *pos += 2;
+    let encoded_width = ((byte(pos)? as usize) << 8) | byte(pos)? as usize;
+    if encoded_width != width {
+        return Err(HdrError::BadScanline);
+    }
+    // every channel is run length encoded separately
+    for channel in 0..4 {
+        let mut x = 0;
+        while x < width {
+            let count = byte(pos)? as usize;
+            if count > 128 {
+                let count = count - 128;
+                if x + count > width {
+                    return Err(HdrError::BadScanline);
+                }
+                let value = byte(pos)?;
+                for pixel in scanline[x..x + count].iter_mut() {
+                    pixel[channel] = value;
+                }
+                x += count;
+            } else {
+                if count == 0 || x + count > width {
+                    return Err(HdrError::BadScanline);
+                }
+                for pixel in scanline[x..x + count].iter_mut() {
+                    pixel[channel] = byte(pos)?;
+                }
+                x += count;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a gradient with a flat band, so that the scanlines have runs and raw bytes
+    fn gradient(width: i32, height: i32) -> HdrImage {
+        let mut img = HdrImage::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                let c = if x < width / 2 { Vec3f(0.5, 0.5, 0.5) } else { Vec3f(x as f32 * 10.0, y as f32 * 0.01, 1.0) };
+                img.set_pixel(x, y, c);
+            }
+        }
+        img
+    }
+
+    #[test]
+    fn test_radiance_bad_size() {
+        for resolution in ["-Y -4 +X 10", "-Y 0 +X 10", "-Y 2000000000 +X 2000000000", "-Y 100000 +X 100000"] {
+            let v = format!("#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n{}\n", resolution).into_bytes();
+            assert_eq!(HdrImage::from_raw_vec(v).err(), Some(HdrError::BadSize), "{}", resolution);
+        }
+        let v = b"#?RADIANCE\n\n+Y 2 +X 2\n".to_vec();
+        assert_eq!(HdrImage::from_raw_vec(v).err(), Some(HdrError::UnsupportedOrientation("+Y 2 +X 2".into())));
+        assert!(matches!(HdrImage::from_file("does/not/exist.hdr".into()), Err(HdrError::Io(_))));
+    }
+
+    #[test]
+    fn test_pfm_round_trip() {
+        let img = gradient(5, 3);
+        let read = HdrImage::from_raw_vec(img.to_pfm()).unwrap();
+        assert_eq!((read.width, read.height), (5, 3));
+        assert_eq!(img.rmse(&read), Some(0.0));
+        // big endian grayscale, bottom row first
+        let mut v = b"Pf\n2 2\n1.0\n".to_vec();
+        for f in [1.0f32, 2.0, 3.0, 4.0] {
+            v.extend_from_slice(&f.to_be_bytes());
+        }
+        let gray = HdrImage::from_raw_vec(v).unwrap();
+        assert_eq!(gray.pixel_at(0, 0).0, 3.0);
+        assert_eq!(gray.pixel_at(1, 1).2, 2.0);
+        assert_eq!(HdrImage::from_raw_vec(b"PF\n2 2\n-1.0\n".to_vec()).err(), Some(HdrError::Truncated));
+    }
+
+    #[test]
+    fn test_pfm_bad_size() {
+        for (header, error) in [
+            ("PF\n-2 2\n-1.0\n", HdrError::BadSize),
+            ("PF\n4294967296 4294967296\n-1.0\n", HdrError::BadSize),
+            ("PF\n65536 65536\n-1.0\n", HdrError::BadSize),
+        ] {
+            assert_eq!(HdrImage::from_raw_vec(header.as_bytes().to_vec()).err(), Some(error), "{}", header);
+        }
+        // a size that fits but whose data is missing
+        let v = b"PF\n30000 30000\n-1.0\n".to_vec();
+        assert_eq!(HdrImage::from_raw_vec(v).err(), Some(HdrError::Truncated));
+    }
+
+    #[test]
+    fn test_radiance_round_trip() {
+        for width in [5, 40] {
+            let img = gradient(width, 4);
+            let read = HdrImage::from_raw_vec(img.to_radiance()).unwrap();
+            assert_eq!((read.width, read.height), (width, 4));
+            // rgbe keeps 8 bits of mantissa
+            for (a, b) in img.data.iter().zip(read.data.iter()) {
+                let d = (*a - *b).length();
+                assert!(d <= a.length() / 128.0, "{:?} {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn test_rmse() {
+        let a = gradient(4, 2);
+        let mut b = gradient(4, 2);
+        b.set_pixel(0, 0, b.pixel_at(0, 0) + Vec3f(0.8, 0.8, 0.8));
+        assert!((a.rmse(&b).unwrap() - (0.64f32 / 8.0).sqrt()).abs() < 1e-6);
+        assert_eq!(a.rmse(&gradient(2, 4)), None);
+    }
+}
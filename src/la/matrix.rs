@@ -64,6 +64,11 @@ impl<const X: usize, const Y: usize> MatrixI<X, Y> for Matrix<X, Y> {
             r
         };
         for y in 0..n {
+            // partial pivoting, e.g. rotations by 90 degrees have zeros on the diagonal
+            let pivot = (y..n)
+                .max_by(|&a, &b| aug[a][y].abs().partial_cmp(&aug[b][y].abs()).unwrap())
+                .unwrap();
+            aug.swap(y, pivot);
             assert!(aug[y][y] != 0.0f32, "it's a bad idea to divide by zero");
             for x in 0..n {
                 if x != y {
@@ -1,4 +1,4 @@
-use std::ops::{Neg, Add, Sub, Mul, Div};
+use std::ops::{Neg, Add, Sub, Mul, Div, Index};
 use rand::distributions::{Distribution, Standard};
 use rand::Rng;
 use lodepng::RGB;
@@ -69,6 +69,19 @@ impl Div<f32> for Vec3f {
     }
 }
 
+impl Index<usize> for Vec3f {
+    type Output = f32;
+
+    fn index(&self, axis: usize) -> &Self::Output {
+        match axis {
+            0 => &self.0,
+            1 => &self.1,
+            2 => &self.2,
+            _ => panic!("Vec3f has only 3 axes, got {}", axis),
+        }
+    }
+}
+
 // generate the standard distribution of vec3f
 impl Distribution<Vec3f> for Standard {
     fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Vec3f {
@@ -187,6 +200,16 @@ impl Vec3f {
         Vec3f(self.0 * v, self.1 * v, self.2 * v)
     }
 
+    // component-wise minimum
+    pub fn min(&self, v: &Vec3f) -> Vec3f {
+        Vec3f(self.0.min(v.0), self.1.min(v.1), self.2.min(v.2))
+    }
+
+    // component-wise maximum
+    pub fn max(&self, v: &Vec3f) -> Vec3f {
+        Vec3f(self.0.max(v.0), self.1.max(v.1), self.2.max(v.2))
+    }
+
     pub fn rotate(&self, x: f32, y: f32) -> Vec3f {
         let xm: Matrix<3, 3> = [
             [1.0, 0.0, 0.0],
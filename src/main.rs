@@ -1,6 +1,10 @@
 #![feature(generic_const_exprs)]
+#![cfg_attr(test, feature(test))]
 #![allow(dead_code)]
 
+#[cfg(test)]
+extern crate test;
+
 #[cfg(not(feature = "raytracing"))]
 extern crate anyhow;
 #[cfg(not(feature = "raytracing"))]
@@ -9,6 +13,7 @@ extern crate yew;
 mod la;
 mod shader;
 mod tga;
+mod hdr;
 mod camera;
 mod transform;
 mod utils;
@@ -22,13 +27,21 @@ mod web;
 
 #[cfg(feature = "raytracing")]
 
-use utils::{random_scene,ray_color};
+use utils::{random_scene,mesh_scene,cornell_box,ray_color};
 use la::Vec3f;
 use camera::CameraTrait;
 use raytracing::{
     ExposureCamera,
     Exposure,
 };
+#[cfg(feature = "raytracing")]
+use raytracing::{materials::Lambertian, textures::ImageTexture, Environment};
+#[cfg(feature = "raytracing")]
+use tga::Image;
+#[cfg(feature = "raytracing")]
+use hdr::HdrImage;
+#[cfg(feature = "raytracing")]
+use std::sync::Arc;
 use indicatif::ProgressBar;
 use lodepng::RGB;
 use rayon::iter::IntoParallelIterator;
@@ -55,11 +68,44 @@ fn main() {
     const SAMPLES_PER_PIXEL: usize = 50;
     const MAX_DEPTH: usize = 200;
     // World
-    let world = random_scene();
+    // pass `cornell` to render the cornell box, or the path of a wavefront model
+    // (e.g. res/african_head/model.obj) to render it instead of the random spheres
+    // an optional second argument is a radiance .hdr file used as the environment map
+    let (mut world, position, view, aperture) = match std::env::args().nth(1).as_deref() {
+        Some("cornell") => (
+            cornell_box(),
+            Vec3f(278.0, 278.0, -299.0),
+            Vec3f(278.0, 278.0, 555.0),
+            0.0,
+        ),
+        Some(file) => {
+            // use the texture.tga or texture.png next to the model when there is one
+            let texture = ["texture.tga", "texture.png"]
+                .iter()
+                .map(|name| Path::new(file).with_file_name(name))
+                .find(|texture| texture.exists());
+            let material = if let Some(texture) = texture {
+                let image = Image::from_file(texture.to_string_lossy().into_owned())
+                    .unwrap_or_else(|e| panic!("failed to read {}: {}", texture.display(), e));
+                Lambertian::with_texture(Arc::new(ImageTexture::new(image)))
+            } else {
+                Lambertian::new(Vec3f(0.7, 0.5, 0.4))
+            };
+            (
+                mesh_scene(file.to_owned(), Arc::new(material))
+                    .unwrap_or_else(|e| panic!("failed to read {}: {}", file, e)),
+                Vec3f(1.0, 0.5, 3.0),
+                Vec3f(0.0,0.0,0.0),
+                0.1,
+            )
+        }
+        None => (random_scene(), Vec3f(13.0, 2.0, 3.0), Vec3f(0.0,0.0,0.0), 0.1),
+    };
+    if let Some(file) = std::env::args().nth(2) {
+        let map = HdrImage::from_file(file).expect("failed to read the environment map");
+        world.environment = Environment::Map(map);
+    }
     let mut camera = ExposureCamera::default();
-    let position = Vec3f(13.0, 2.0, 3.0);
-    let view = Vec3f(0.0,0.0,0.0);
-    let aperture = 0.1;
     camera.set_aspect(ASPECT);
     camera.set_aperture(aperture);
     camera.set_focus(view);
@@ -68,10 +114,9 @@ fn main() {
     // Progress bar
     let bar = ProgressBar::new(HEIGHT as u64);
 
-    // Render
-    let white = (256.0 * f32::clamp(1.0, 0.0, 0.999)) as u8;
-    let mut pixels: Vec<RGB<u8>> = vec![RGB::new(white, white, white); WIDTH * HEIGHT];
-    let bands: Vec<(usize, &mut [RGB<u8>])> = pixels.chunks_mut(WIDTH).enumerate().collect();
+    // Render the average radiance of the samples of each pixel
+    let mut radiance = HdrImage::new(WIDTH as i32, HEIGHT as i32);
+    let bands: Vec<(usize, &mut [Vec3f])> = radiance.data.chunks_mut(WIDTH).enumerate().collect();
     bands.into_par_iter().for_each(|(row, band)| {
         let height = HEIGHT - row;
         let mut rng = rand::thread_rng();
@@ -83,8 +128,7 @@ fn main() {
                 let r = camera.exposure_ray(u, v);
                 pixel_color = pixel_color + ray_color(&r, &world, MAX_DEPTH);
             }
-            let pixel = pixel_color.to_rgb_sampled(SAMPLES_PER_PIXEL);
-            band[column] = pixel;
+            band[column] = pixel_color / SAMPLES_PER_PIXEL as f32;
         }
         bar.inc(1);
     });
@@ -92,10 +136,18 @@ fn main() {
     bar.finish();
 
     let path = &Path::new("image.png");
+    let pixels: Vec<RGB<u8>> = radiance.data.iter().map(|c| c.to_rgb_sampled(1)).collect();
 
     if let Err(e) = lodepng::encode_file(path, &pixels, WIDTH, HEIGHT, lodepng::ColorType::RGB, 8) {
         panic!("failed to write png: {:?}", e);
     }
+    // the radiance itself, for post-processing and comparing renders
+    if let Err(e) = radiance.write_to_radiance("image.hdr") {
+        panic!("failed to write image.hdr: {:?}", e);
+    }
+    if let Err(e) = radiance.write_to_pfm("image.pfm") {
+        panic!("failed to write image.pfm: {:?}", e);
+    }
 
-    println!("Written to {}", path.display());
+    println!("Written to {}, image.hdr and image.pfm", path.display());
 }
\ No newline at end of file
@@ -1,23 +1,202 @@
-use std::fs;
+use std::collections::HashMap;
+use std::path::Path;
+use std::{fmt, fs};
 
 use crate::{
     la::Vec3f,
-    tga::{Color, Image},
+    tga::{Color, Image, MipMap, Sampler},
+    transform::Transform,
 };
 
+use super::{load_materials, MtlMaterial, PbrMaterial, SurfaceMaterial};
+
+// how the normal map encodes the normals
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NormalSpace {
+    // object space normals, like the bundled `normals.tga`
+    Object,
+    // relative to the surface, e.g. `*_nm_tangent.tga`
+    Tangent,
+}
+
 pub struct MeshObject {
     pub model: Wavefront,
     pub normal_map: Image,
     pub texture: Image,
+    // the mip chains of the textures above, and how they are sampled
+    pub normal_mips: MipMap,
+    pub texture_mips: MipMap,
+    pub sampler: Sampler,
+    // the position, rotation and scale of the object in the scene
+    pub transform: Transform,
+    // the material used by the pbr shader
+    pub material: PbrMaterial,
+    pub normal_space: NormalSpace,
+    // tangent, bitangent and normal of the vertices of each face, for tangent space normal maps
+    pub tangent_frames: Vec<[[Vec3f; 3]; 3]>,
+    // the mtl materials of the model, in the order of `Wavefront::materials`.
+    // The faces of a material use it instead of the textures and material above
+    pub surfaces: Vec<SurfaceMaterial>,
 }
 
 impl MeshObject {
     pub fn new(wf: Wavefront, nm: Image, txt: Image) -> Self {
         MeshObject {
             model: wf,
+            normal_mips: MipMap::new(&nm),
+            texture_mips: MipMap::new(&txt),
             normal_map: nm,
             texture: txt,
+            sampler: Sampler::default(),
+            transform: Transform::default(),
+            material: PbrMaterial::default(),
+            normal_space: NormalSpace::Object,
+            tangent_frames: Vec::new(),
+            surfaces: Vec::new(),
+        }
+    }
+
+    // read a wavefront model with what is next to it: texture.tga or texture.png,
+    // a normal map and the mtl libraries of the model. The normal map is in tangent space if it is
+    // normals_tangent.tga or *_nm_tangent.tga, in object space if it is normals.tga
+    pub fn from_file(file: String) -> Result<Self, ObjError> {
+        let dir = Path::new(&file).parent().unwrap_or_else(|| Path::new("")).to_owned();
+        let wf = Wavefront::parse_file(file)?;
+        let materials = load_materials(&wf, &dir)?;
+        // the first of the files that exists, an empty image if none does
+        let map = |names: &[&str]| -> Result<Option<Image>, ObjError> {
+            names
+                .iter()
+                .map(|name| dir.join(name))
+                .find(|path| path.is_file())
+                .map(|path| {
+                    Image::from_file(path.to_string_lossy().into_owned()).map_err(|e| ObjError {
+                        line: 0,
+                        message: format!("could not read {}: {}", path.display(), e),
+                    })
+                })
+                .transpose()
+        };
+        let texture = map(&["texture.tga", "texture.png"])?.unwrap_or_else(|| Image::new(0, 0));
+        let tangent_maps: Vec<String> = fs::read_dir(&dir)
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+                    .filter(|name| name == "normals_tangent.tga" || name.ends_with("_nm_tangent.tga"))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let tangent_maps: Vec<&str> = tangent_maps.iter().map(|name| name.as_str()).collect();
+        let (normals, normal_space) = match map(&tangent_maps)? {
+            Some(normals) => (normals, NormalSpace::Tangent),
+            None => (map(&["normals.tga"])?.unwrap_or_else(|| Image::new(0, 0)), NormalSpace::Object),
+        };
+        Ok(MeshObject::new(wf, normals, texture)
+            .with_normal_space(normal_space)
+            .with_materials(&materials, &dir))
+    }
+
+    pub fn with_transform(self, transform: Transform) -> Self {
+        MeshObject { transform, ..self }
+    }
+
+    pub fn with_sampler(self, sampler: Sampler) -> Self {
+        MeshObject { sampler, ..self }
+    }
+
+    pub fn with_material(self, material: PbrMaterial) -> Self {
+        MeshObject { material, ..self }
+    }
+
+    // select how the normal map is read
+    pub fn with_normal_space(self, normal_space: NormalSpace) -> Self {
+        MeshObject { normal_space, ..self }.update_tangent_frames()
+    }
+
+    pub fn with_surfaces(self, surfaces: Vec<SurfaceMaterial>) -> Self {
+        MeshObject { surfaces, ..self }.update_tangent_frames()
+    }
+
+    // use the mtl materials of the model (see `load_materials`), their maps are read from dir
+    pub fn with_materials(self, materials: &[MtlMaterial], dir: &Path) -> Self {
+        let surfaces = materials.iter().map(|m| m.surface(dir)).collect();
+        self.with_surfaces(surfaces)
+    }
+
+    // the tangent frames are needed by the tangent space normal maps, and the maps of the surfaces
+    fn update_tangent_frames(self) -> Self {
+        let tangent = self.normal_space == NormalSpace::Tangent || self.surfaces.iter().any(|s| s.normal_map.is_some());
+        let tangent_frames = if tangent { self.compute_tangent_frames() } else { Vec::new() };
+        MeshObject { tangent_frames, ..self }
+    }
+
+    // the surface material of a face, None if it uses the textures and material of the object
+    pub fn surface(&self, iface: usize) -> Option<&SurfaceMaterial> {
+        self.surfaces.get(self.model.face_material(iface)?)
+    }
+
+    // accumulate the tangents and bitangents of the faces from their u,v, then orthonormalize
+    // them against the vertex normals (the averaged face normals if the model has none)
+    // vertices are shared by the faces with the same position and u,v, so the uv seams keep their own frames
+    fn compute_tangent_frames(&self) -> Vec<[[Vec3f; 3]; 3]> {
+        let mut frames: HashMap<(i32, i32), [Vec3f; 3]> = HashMap::new();
+        for f in 0..self.num_faces() {
+            let (vertices, texture) = self.model.faces[f];
+            let p = [self.vertex(f, 0), self.vertex(f, 1), self.vertex(f, 2)];
+            let t = [self.texture_coords(f, 0), self.texture_coords(f, 1), self.texture_coords(f, 2)];
+            let e1 = p[1].sub(&p[0]);
+            let e2 = p[2].sub(&p[0]);
+            let (du1, dv1) = (t[1][0] - t[0][0], t[1][1] - t[0][1]);
+            let (du2, dv2) = (t[2][0] - t[0][0], t[2][1] - t[0][1]);
+            let det = du1 * dv2 - du2 * dv1;
+            // weight by the area, like the face normal
+            let face_normal = e1.cross(&e2);
+            let (tangent, bitangent) = if det.abs() < 1e-12 {
+                (Vec3f::zeroed(), Vec3f::zeroed())
+            } else {
+                let r = 1.0 / det;
+                (
+                    e1.mulf(dv2 * r).sub(&e2.mulf(dv1 * r)),
+                    e2.mulf(du1 * r).sub(&e1.mulf(du2 * r)),
+                )
+            };
+            for v in 0..3 {
+                let normal = self
+                    .model
+                    .vertex_normal(f, v)
+                    .map(|n| n.mulf(face_normal.length()))
+                    .unwrap_or(face_normal);
+                let frame = frames
+                    .entry((vertices[v], texture[v]))
+                    .or_insert([Vec3f::zeroed(); 3]);
+                frame[0] = frame[0].add(&tangent);
+                frame[1] = frame[1].add(&bitangent);
+                frame[2] = frame[2].add(&normal);
+            }
         }
+        for frame in frames.values_mut() {
+            let n = frame[2].normalize();
+            // Gram-Schmidt, keep the handedness of the uv mapping
+            let t = frame[0].sub(&n.mulf(n.mul(&frame[0]))).normalize();
+            let handedness = if n.cross(&t).mul(&frame[1]) < 0.0 { -1.0 } else { 1.0 };
+            *frame = [t, n.cross(&t).mulf(handedness), n];
+        }
+        self.model
+            .faces
+            .iter()
+            .map(|(vertices, texture)| {
+                [0, 1, 2].map(|v| frames[&(vertices[v], texture[v])])
+            })
+            .collect()
+    }
+
+    // the tangent frame of a vertex of a face, in object space
+    pub fn tangent_frame(&self, iface: usize, nvert: usize) -> [Vec3f; 3] {
+        self.tangent_frames[iface][nvert]
+    }
+
+    pub fn has_tangent_frames(&self) -> bool {
+        !self.tangent_frames.is_empty()
     }
 
     pub fn screen_texture_model() -> Self {
@@ -32,9 +211,21 @@ impl MeshObject {
                 texture_coord: vec![[-1.0, -1.0], [1.0, -1.0], [1.0, 1.0], [-1.0, 1.0]],
                 normals: Vec::new(),
                 faces: vec![([3, 0, 1], [3, 0, 1]), ([3, 1, 2], [3, 1, 2])],
+                face_normals: vec![None, None],
+                material_libs: Vec::new(),
+                materials: Vec::new(),
+                face_materials: Vec::new(),
             },
             normal_map: Image::new(0, 0),
             texture: Image::new(0, 0),
+            normal_mips: MipMap::new(&Image::new(0, 0)),
+            texture_mips: MipMap::new(&Image::new(0, 0)),
+            sampler: Sampler::default(),
+            transform: Transform::default(),
+            material: PbrMaterial::default(),
+            normal_space: NormalSpace::Object,
+            tangent_frames: Vec::new(),
+            surfaces: Vec::new(),
         }
     }
 
@@ -48,22 +239,25 @@ impl MeshObject {
     }
 
     pub fn texture_coords(&self, iface: usize, nvert: usize) -> [f32; 2] {
-        let (_, texture) = self.model.faces.get(iface).unwrap();
-        self.model.texture_coord[texture[nvert] as usize]
+        self.model.texture_coords(iface, nvert).unwrap_or([0.0, 0.0])
     }
 
-    pub fn texture(&self, u: f32, v: f32) -> Color {
-        self.texture.pixel_at(
-            (u * self.texture.width as f32).round() as i32,
-            (v * self.texture.height as f32).round() as i32,
-        )
+    // the level of detail of an image for a pixel covering `footprint` in u,v
+    pub fn lod(img: &Image, footprint: f32) -> f32 {
+        (footprint * img.width.max(img.height) as f32).max(1.0).log2()
+    }
+
+    pub fn texture(&self, u: f32, v: f32, footprint: f32) -> Color {
+        self.texture_mips.sample(&self.sampler, u, v, MeshObject::lod(&self.texture, footprint))
+    }
+
+    pub fn normal(&self, u: f32, v: f32, footprint: f32) -> Vec3f {
+        let normal = self.normal_mips.sample(&self.sampler, u, v, MeshObject::lod(&self.normal_map, footprint));
+        MeshObject::decode_normal(normal)
     }
 
-    pub fn normal(&self, u: f32, v: f32) -> Vec3f {
-        let normal = self.normal_map.pixel_at(
-            (u * self.normal_map.width as f32).round() as i32,
-            (v * self.normal_map.height as f32).round() as i32,
-        );
+    // the normal stored in a pixel of a normal map
+    pub fn decode_normal(normal: Color) -> Vec3f {
         Vec3f(
             ((normal.2 as f32 / 255.0) * 2.) - 1.,
             ((normal.1 as f32 / 255.0) * 2.) - 1.,
@@ -83,14 +277,24 @@ pub struct Wavefront {
     pub normals: Vec<Vec3f>,
     // faces:
     // 0: the ids of vertexs (3 vertices)
-    // 1: the idx of texture points (3 vertices)
+    // 1: the idx of texture points (3 vertices), -1 if the vertex has no `vt`
     pub faces: Vec<([i32; 3], [i32; 3])>,
+    // the idx of normal vectors of each face (same order as faces),
+    // None if the face does not reference any `vn`
+    pub face_normals: Vec<Option<[i32; 3]>>,
+    // the material libraries named by `mtllib`
+    pub material_libs: Vec<String>,
+    // the material names of `usemtl`, in the order they are first used
+    pub materials: Vec<String>,
+    // the idx in materials of each face (same order as faces), None before the first `usemtl`
+    pub face_materials: Vec<Option<usize>>,
 }
 
 impl Wavefront {
     pub fn new(
         vertices: Vec<Vec3f>,
         faces: Vec<([i32; 3], [i32; 3])>,
+        face_normals: Vec<Option<[i32; 3]>>,
         normals: Vec<Vec3f>,
         texture_coord: Vec<[f32; 2]>,
     ) -> Self {
@@ -99,75 +303,249 @@ impl Wavefront {
             texture_coord,
             normals,
             faces,
+            face_normals,
+            material_libs: Vec::new(),
+            materials: Vec::new(),
+            face_materials: Vec::new(),
         }
     }
 
-    pub fn parse_file(file: String) -> Self {
-        let contents = fs::read_to_string(file).expect("Something went wrong reading the file");
-        Wavefront::parse_string(contents)
+    pub fn num_faces(&self) -> usize {
+        self.faces.len()
+    }
+
+    pub fn vertex(&self, iface: usize, nvert: usize) -> Vec3f {
+        let (vertices, _) = self.faces[iface];
+        self.vertices[vertices[nvert] as usize]
+    }
+
+    // the u,v coordinates of the vertex, None if the model has no `vt` data
+    pub fn texture_coords(&self, iface: usize, nvert: usize) -> Option<[f32; 2]> {
+        let (_, texture) = self.faces.get(iface)?;
+        self.texture_coord.get(texture[nvert] as usize).copied()
+    }
+
+    // the normal of the vertex from the `vn` data, None if the face has no normals
+    pub fn vertex_normal(&self, iface: usize, nvert: usize) -> Option<Vec3f> {
+        let normals = self.face_normals.get(iface).copied().flatten()?;
+        self.normals.get(normals[nvert] as usize).copied()
+    }
+
+    // the idx in materials of the material of a face
+    pub fn face_material(&self, iface: usize) -> Option<usize> {
+        self.face_materials.get(iface).copied().flatten()
     }
 
-    pub fn parse_string(contents: String) -> Self {
-        // let contents = fs::read_to_string(file).expect("Something went wrong reading the file");
-        let lines = contents.lines();
+    pub fn parse_file(file: String) -> Result<Self, ObjError> {
+        let contents = fs::read_to_string(&file).map_err(|e| ObjError {
+            line: 0,
+            message: format!("could not read {}: {}", file, e),
+        })?;
+        Wavefront::parse_string(&contents)
+    }
+
+    // polygons are split in triangle fans, groups, objects and smoothing are skipped
+    pub fn parse_string(contents: &str) -> Result<Self, ObjError> {
         let mut vertices: Vec<Vec3f> = Vec::new();
         let mut normals: Vec<Vec3f> = Vec::new();
         let mut tc: Vec<[f32; 2]> = Vec::new();
         let mut faces: Vec<([i32; 3], [i32; 3])> = Vec::new();
-        for l in lines {
-            let lc = l.trim();
-            if lc.starts_with("#") || l.is_empty() {
-                continue;
-            }
-            if lc.starts_with("v ") {
-                let mut items = lc.split_ascii_whitespace();
-                items.next().unwrap(); // v
-                vertices.push(Vec3f(
-                    items.next().unwrap().parse().unwrap(),
-                    items.next().unwrap().parse().unwrap(),
-                    items.next().unwrap().parse().unwrap(),
-                ))
-            }
-            if lc.starts_with("vn ") {
-                let mut items = lc.split_ascii_whitespace();
-                items.next().unwrap(); // vn
-                normals.push(Vec3f(
-                    items.next().unwrap().parse().unwrap(),
-                    items.next().unwrap().parse().unwrap(),
-                    items.next().unwrap().parse().unwrap(),
-                ))
-            }
-            if lc.starts_with("vt ") {
-                let mut items = lc.split_ascii_whitespace();
-                items.next().unwrap(); // vt
-                tc.push([
-                    items.next().unwrap().parse().unwrap(),
-                    items.next().unwrap().parse().unwrap(),
-                ])
-            }
-            if lc.starts_with("f ") {
-                let mut items = lc.split_ascii_whitespace();
-                items.next().unwrap(); // f
-                let mut f1 = items.next().unwrap().split("/");
-                let mut f2 = items.next().unwrap().split("/");
-                let mut f3 = items.next().unwrap().split("/");
-
-                faces.push((
-                    [
-                        f1.next().unwrap().parse::<i32>().unwrap() - 1,
-                        f2.next().unwrap().parse::<i32>().unwrap() - 1,
-                        f3.next().unwrap().parse::<i32>().unwrap() - 1,
-                    ],
-                    [
-                        f1.next().unwrap().parse::<i32>().unwrap() - 1,
-                        f2.next().unwrap().parse::<i32>().unwrap() - 1,
-                        f3.next().unwrap().parse::<i32>().unwrap() - 1,
-                    ],
-                ))
+        let mut face_normals: Vec<Option<[i32; 3]>> = Vec::new();
+        let mut material_libs: Vec<String> = Vec::new();
+        let mut materials: Vec<String> = Vec::new();
+        let mut face_materials: Vec<Option<usize>> = Vec::new();
+        let mut material: Option<usize> = None;
+        for (i, l) in contents.lines().enumerate() {
+            let err = |message: String| ObjError { line: i + 1, message };
+            // drop the comments
+            let lc = l.split('#').next().unwrap_or("").trim();
+            let mut items = lc.split_ascii_whitespace();
+            match items.next() {
+                Some("v") => {
+                    let v = parse_floats(items, 3).map_err(err)?;
+                    vertices.push(Vec3f(v[0], v[1], v[2]));
+                }
+                Some("vn") => {
+                    let n = parse_floats(items, 3).map_err(err)?;
+                    normals.push(Vec3f(n[0], n[1], n[2]));
+                }
+                Some("vt") => {
+                    // v is optional, and w is not used
+                    let t = parse_floats(items, 1).map_err(err)?;
+                    tc.push([t[0], t.get(1).copied().unwrap_or(0.0)]);
+                }
+                Some("f") => {
+                    let counts = (vertices.len(), tc.len(), normals.len());
+                    let corners = items
+                        .map(|c| parse_corner(c, counts))
+                        .collect::<Result<Vec<_>, _>>()
+                        .map_err(err)?;
+                    if corners.len() < 3 {
+                        return Err(err(format!("a face needs 3 vertices, found {}", corners.len())));
+                    }
+                    for k in 1..corners.len() - 1 {
+                        let c = [corners[0], corners[k], corners[k + 1]];
+                        // -1 for the corners without u,v
+                        faces.push((c.map(|c| c.0), c.map(|c| c.1.unwrap_or(-1))));
+                        face_normals.push(match c.map(|c| c.2) {
+                            [Some(n1), Some(n2), Some(n3)] => Some([n1, n2, n3]),
+                            _ => None,
+                        });
+                        face_materials.push(material);
+                    }
+                }
+                Some("mtllib") => material_libs.extend(items.map(String::from)),
+                Some("usemtl") => {
+                    let name = items.next().ok_or_else(|| err("usemtl without a material name".into()))?;
+                    material = Some(match materials.iter().position(|m| m == name) {
+                        Some(m) => m,
+                        None => {
+                            materials.push(name.to_owned());
+                            materials.len() - 1
+                        }
+                    });
+                }
+                _ => {}
             }
         }
 
-        Wavefront::new(vertices, faces, normals, tc)
+        Ok(Wavefront {
+            material_libs,
+            materials,
+            face_materials,
+            ..Wavefront::new(vertices, faces, face_normals, normals, tc)
+        })
+    }
+}
+
+// an error in an obj file, line is counted from 1 (0 if the file could not be read)
+#[derive(Clone, Debug, PartialEq)]
+pub struct ObjError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+// the numbers after the keyword of a line, at least `min` of them
+pub fn parse_floats<'a>(items: impl Iterator<Item = &'a str>, min: usize) -> Result<Vec<f32>, String> {
+    let v = items
+        .map(|x| x.parse::<f32>().map_err(|_| format!("invalid number `{}`", x)))
+        .collect::<Result<Vec<_>, _>>()?;
+    if v.len() < min {
+        return Err(format!("expected {} numbers, found {}", min, v.len()));
+    }
+    Ok(v)
+}
+
+// the vertex, u,v and normal indices of a corner of a face: v, v/vt, v//vn or v/vt/vn
+// counts: the number of v, vt and vn read so far
+fn parse_corner(corner: &str, counts: (usize, usize, usize)) -> Result<(i32, Option<i32>, Option<i32>), String> {
+    let mut parts = corner.split('/');
+    let v = parse_index(parts.next().unwrap_or(""), counts.0, "vertex")?;
+    let vt = match parts.next() {
+        None | Some("") => None,
+        Some(t) => Some(parse_index(t, counts.1, "texture")?),
+    };
+    let vn = match parts.next() {
+        None | Some("") => None,
+        Some(n) => Some(parse_index(n, counts.2, "normal")?),
+    };
+    if parts.next().is_some() {
+        return Err(format!("invalid face vertex `{}`", corner));
+    }
+    Ok((v, vt, vn))
+}
+
+// the index from 0 of an obj index, from 1, or from the end if negative
+fn parse_index(index: &str, count: usize, what: &str) -> Result<i32, String> {
+    let i: i64 = index.parse().map_err(|_| format!("invalid {} index `{}`", what, index))?;
+    let idx = if i < 0 { count as i64 + i } else { i - 1 };
+    if i == 0 || idx < 0 || idx >= count as i64 {
+        return Err(format!("{} index {} out of range, {} defined", what, i, count));
     }
+    Ok(idx as i32)
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nvt 0 0\nvt 1 0\nvt 1 1\nvt 0 1\nvn 0 0 1\n";
+
+    #[test]
+    fn test_index_forms() {
+        for (face, texture, normals) in [
+            ("f 1 2 3", [-1, -1, -1], None),
+            ("f 1/1 2/2 3/3", [0, 1, 2], None),
+            ("f 1//1 2//1 3//1", [-1, -1, -1], Some([0, 0, 0])),
+            ("f 1/1/1 2/2/1 3/3/1", [0, 1, 2], Some([0, 0, 0])),
+            ("f -4/-4/-1 -3/-3/-1 -2/-2/-1", [0, 1, 2], Some([0, 0, 0])),
+        ] {
+            let wf = Wavefront::parse_string(&format!("{}{}\n", HEADER, face)).unwrap();
+            assert_eq!(wf.faces, vec![([0, 1, 2], texture)], "{}", face);
+            assert_eq!(wf.face_normals, vec![normals], "{}", face);
+        }
+        let wf = Wavefront::parse_string(&format!("{}f 1 2 3\n", HEADER)).unwrap();
+        assert_eq!(wf.texture_coords(0, 0), None);
+    }
+
+    #[test]
+    fn test_polygons() {
+        let wf = Wavefront::parse_string(&format!("{}f 1/1 2/2 3/3 4/4 # a quad\n", HEADER)).unwrap();
+        assert_eq!(wf.faces, vec![([0, 1, 2], [0, 1, 2]), ([0, 2, 3], [0, 2, 3])]);
+        assert_eq!(wf.face_normals, vec![None, None]);
+    }
+
+    #[test]
+    fn test_errors() {
+        for (text, line) in [
+            ("v 0 0\n", 1),
+            ("v 0 0 0\n\nv 0 x 0\n", 3),
+            ("v 0 0 0\nv 1 0 0\nf 1 2\n", 3),
+            ("v 0 0 0\nv 1 0 0\nf 1 2 3\n", 3),
+            ("v 0 0 0\nv 1 0 0\nv 1 1 0\nf 0 1 2\n", 4),
+            ("v 0 0 0\nv 1 0 0\nv 1 1 0\nf -4 1 2\n", 4),
+            ("v 0 0 0\nv 1 0 0\nv 1 1 0\nf 1/1 2/1 3/1\n", 4),
+            ("v 0 0 0\nv 1 0 0\nv 1 1 0\nf 1/a 2 3\n", 4),
+            ("v 0 0 0\nv 1 0 0\nv 1 1 0\nf 1/1/1/1 2 3\n", 4),
+        ] {
+            assert_eq!(Wavefront::parse_string(text).unwrap_err().line, line, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn test_tangent_frames() {
+        // the quad of HEADER with u along x and v along y, then with u along y and v along -x
+        for (uvs, tangent, bitangent) in [
+            ("vt 0 0\nvt 1 0\nvt 1 1\nvt 0 1\n", Vec3f(1.0, 0.0, 0.0), Vec3f(0.0, 1.0, 0.0)),
+            ("vt 0 0\nvt 0 -1\nvt 1 -1\nvt 1 0\n", Vec3f(0.0, 1.0, 0.0), Vec3f(-1.0, 0.0, 0.0)),
+        ] {
+            let text = format!("v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\n{}vn 0 0 1\nf 1/1/1 2/2/1 3/3/1 4/4/1\n", uvs);
+            let wf = Wavefront::parse_string(&text).unwrap();
+            let model = MeshObject::new(wf, Image::new(0, 0), Image::new(0, 0));
+            assert!(!model.has_tangent_frames());
+            let model = model.with_normal_space(NormalSpace::Tangent);
+            let close = |a: &Vec3f, b: &Vec3f| a.sub(b).length() < 1e-5;
+            for f in 0..model.num_faces() {
+                for v in 0..3 {
+                    let [t, b, n] = model.tangent_frame(f, v);
+                    assert!(close(&t, &tangent) && close(&b, &bitangent), "{:?} {:?} for {}", t, b, uvs);
+                    assert!(close(&n, &Vec3f(0.0, 0.0, 1.0)));
+                    // orthonormal
+                    for (x, y) in [(t, b), (b, n), (n, t)] {
+                        assert!(x.mul(&y).abs() < 1e-5);
+                    }
+                    for x in [t, b, n] {
+                        assert!((x.length() - 1.0).abs() < 1e-5);
+                    }
+                }
+            }
+        }
+    }
+}
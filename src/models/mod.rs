@@ -1,6 +1,10 @@
 mod traits;
 mod mesh_object;
+mod pbr_material;
+mod mtl;
 pub mod objects;
 
 pub use traits::MeshTrait;
-pub use mesh_object::{MeshObject,Wavefront};
+pub use mesh_object::{MeshObject,NormalSpace,ObjError,Wavefront};
+pub use pbr_material::{PbrMaterial,PbrParam};
+pub use mtl::{load_materials,MtlMaterial,SurfaceMaterial};
@@ -0,0 +1,360 @@
+use std::{fs, path::Path, sync::Arc};
+
+use crate::{
+    la::Vec3f,
+    raytracing::{
+        materials::{Dielectric, DiffuseLight, Lambertian, Material, Metal},
+        textures::{ImageTexture, SolidColor, Texture},
+    },
+    tga::{Color, Image, MipMap, Sampler},
+};
+
+use super::mesh_object::parse_floats;
+use super::{MeshObject, ObjError, PbrMaterial, PbrParam, Wavefront};
+
+// a material of a wavefront .mtl library, the colors are rgb in [0,1]
+#[derive(Clone, Debug)]
+pub struct MtlMaterial {
+    pub name: String,
+    // Kd, Ks and Ke
+    pub diffuse: Vec3f,
+    pub specular: Vec3f,
+    pub emission: Vec3f,
+    // Ns, the exponent of the phong highlight
+    pub shininess: f32,
+    // d, 1 is opaque (Tr is 1 - d)
+    pub dissolve: f32,
+    // Ni, the index of refraction
+    pub ior: f32,
+    // the files of map_Kd, map_Bump and map_Ks, relative to the library
+    pub diffuse_map: Option<String>,
+    pub bump_map: Option<String>,
+    pub specular_map: Option<String>,
+}
+
+impl Default for MtlMaterial {
+    fn default() -> Self {
+        MtlMaterial {
+            name: String::new(),
+            diffuse: Vec3f(0.8, 0.8, 0.8),
+            specular: Vec3f::zeroed(),
+            emission: Vec3f::zeroed(),
+            shininess: 0.0,
+            dissolve: 1.0,
+            ior: 1.0,
+            diffuse_map: None,
+            bump_map: None,
+            specular_map: None,
+        }
+    }
+}
+
+// an mtl material for the raster shaders
+pub struct SurfaceMaterial {
+    // for the pbr shader, map_Ks is the specular level
+    pub material: PbrMaterial,
+    // the phong exponent of the basic shader
+    pub shininess: f32,
+    // in the channel order of the images
+    pub emission: Vec3f,
+    // map_Kd and map_Bump with their mip chains, the bump map is a tangent space normal map
+    pub texture: Option<MipMap>,
+    pub normal_map: Option<MipMap>,
+}
+
+impl SurfaceMaterial {
+    // map_Kd, or Kd without a map
+    pub fn color(&self, sampler: &Sampler, u: f32, v: f32, footprint: f32) -> Color {
+        self.texture(sampler, u, v, footprint).unwrap_or_else(|| {
+            let c = self.material.albedo.value(u, v).mulf(255.0);
+            Color(c.0 as u8, c.1 as u8, c.2 as u8)
+        })
+    }
+
+    // add the light given off by the surface to a shaded color
+    pub fn emit(&self, c: Color) -> Color {
+        let add = |c: u8, e: f32| (c as f32 + e * 255.0).min(255.0) as u8;
+        Color(add(c.0, self.emission.0), add(c.1, self.emission.1), add(c.2, self.emission.2))
+    }
+
+    pub fn texture(&self, sampler: &Sampler, u: f32, v: f32, footprint: f32) -> Option<Color> {
+        let mips = self.texture.as_ref()?;
+        Some(mips.sample(sampler, u, v, MeshObject::lod(&mips.levels[0], footprint)))
+    }
+
+    // the normal in tangent space
+    pub fn normal(&self, sampler: &Sampler, u: f32, v: f32, footprint: f32) -> Option<Vec3f> {
+        let mips = self.normal_map.as_ref()?;
+        let c = mips.sample(sampler, u, v, MeshObject::lod(&mips.levels[0], footprint));
+        Some(MeshObject::decode_normal(c))
+    }
+}
+
+impl MtlMaterial {
+    pub fn parse_file(file: String) -> Result<Vec<Self>, ObjError> {
+        let contents = fs::read_to_string(&file).map_err(|e| ObjError {
+            line: 0,
+            message: format!("could not read {}: {}", file, e),
+        })?;
+        MtlMaterial::parse_string(&contents)
+    }
+
+    // the materials of a library, in order. The options of the maps are skipped
+    pub fn parse_string(contents: &str) -> Result<Vec<Self>, ObjError> {
+        let mut materials: Vec<MtlMaterial> = Vec::new();
+        for (i, l) in contents.lines().enumerate() {
+            let err = |message: String| ObjError { line: i + 1, message };
+            let lc = l.split('#').next().unwrap_or("").trim();
+            let mut items = lc.split_ascii_whitespace();
+            let key = match items.next() {
+                Some(key) => key,
+                None => continue,
+            };
+            if key == "newmtl" {
+                let name = items.next().ok_or_else(|| err("newmtl without a material name".into()))?;
+                materials.push(MtlMaterial { name: name.to_owned(), ..MtlMaterial::default() });
+                continue;
+            }
+            let m = match materials.last_mut() {
+                Some(m) => m,
+                None => return Err(err(format!("{} before newmtl", key))),
+            };
+            // the file name of a map is the last item, after the options
+            let map = || lc.split_ascii_whitespace().last().map(String::from);
+            match key {
+                "Kd" => m.diffuse = parse_color(items).map_err(err)?,
+                "Ks" => m.specular = parse_color(items).map_err(err)?,
+                "Ke" => m.emission = parse_color(items).map_err(err)?,
+                "Ns" => m.shininess = parse_floats(items, 1).map_err(err)?[0],
+                "d" => m.dissolve = parse_floats(items, 1).map_err(err)?[0],
+                "Tr" => m.dissolve = 1.0 - parse_floats(items, 1).map_err(err)?[0],
+                "Ni" => m.ior = parse_floats(items, 1).map_err(err)?[0],
+                "map_Kd" | "map_Bump" | "map_bump" | "bump" | "map_Ks" if items.next().is_none() => {
+                    return Err(err(format!("{} without a file name", key)));
+                }
+                "map_Kd" => m.diffuse_map = map(),
+                "map_Bump" | "map_bump" | "bump" => m.bump_map = map(),
+                "map_Ks" => m.specular_map = map(),
+                _ => {}
+            }
+        }
+        Ok(materials)
+    }
+
+    // how much the material looks like a metal: metals have no diffuse color,
+    // so the more Ks outweighs Kd the more metallic
+    pub fn metallic(&self) -> f32 {
+        let ks = self.specular.0.max(self.specular.1).max(self.specular.2);
+        let kd = self.diffuse.0.max(self.diffuse.1).max(self.diffuse.2);
+        if ks <= 0.0 {
+            0.0
+        } else {
+            (1.0 - kd / ks).clamp(0.0, 1.0)
+        }
+    }
+
+    // the roughness of the GGX distribution closest to the phong exponent
+    pub fn roughness(&self) -> f32 {
+        (2.0 / (self.shininess.max(0.0) + 2.0)).powf(0.25)
+    }
+
+    // the material of the raster shaders, the maps are read from dir
+    pub fn surface(&self, dir: &Path) -> SurfaceMaterial {
+        let metallic = self.metallic();
+        // metals reflect with the specular color
+        let albedo = self.diffuse.mulf(1.0 - metallic).add(&self.specular.mulf(metallic));
+        let specular = (self.specular.0 + self.specular.1 + self.specular.2) / 3.0;
+        SurfaceMaterial {
+            material: PbrMaterial {
+                specular: match read_map(dir, &self.specular_map) {
+                    Some(img) => PbrParam::Texture(img),
+                    None => PbrParam::Constant(Vec3f(specular, specular, specular)),
+                },
+                ..PbrMaterial::new(
+                    PbrParam::Constant(bgr(&albedo)),
+                    PbrParam::Constant(Vec3f(metallic, metallic, metallic)),
+                    PbrParam::Constant(Vec3f(1.0, 1.0, 1.0).mulf(self.roughness())),
+                )
+            },
+            shininess: self.shininess.max(1.0),
+            emission: bgr(&self.emission),
+            texture: read_map(dir, &self.diffuse_map).map(|img| MipMap::new(&img)),
+            normal_map: read_map(dir, &self.bump_map).map(|img| MipMap::new(&img)),
+        }
+    }
+
+    // the material of the path tracer: emissive, transparent, metal or diffuse, the maps are read from dir
+    pub fn raytracing_material(&self, dir: &Path) -> Arc<dyn Material> {
+        if self.emission.0 > 0.0 || self.emission.1 > 0.0 || self.emission.2 > 0.0 {
+            return Arc::new(DiffuseLight::new(self.emission));
+        }
+        if self.dissolve < 1.0 {
+            return Arc::new(Dielectric::new(self.ior));
+        }
+        let texture = |map: &Option<String>, color: Vec3f| -> Arc<dyn Texture> {
+            match read_map(dir, map) {
+                Some(img) => Arc::new(ImageTexture::new(img)),
+                None => Arc::new(SolidColor::new(color)),
+            }
+        };
+        if self.metallic() >= 0.5 {
+            let roughness = self.roughness();
+            Arc::new(Metal::with_texture(texture(&self.specular_map, self.specular), roughness * roughness))
+        } else {
+            Arc::new(Lambertian::with_texture(texture(&self.diffuse_map, self.diffuse)))
+        }
+    }
+}
+
+// read the libraries of a model from dir, and return their materials in the order of
+// `Wavefront::materials`, the names missing from the libraries get the default material
+pub fn load_materials(wf: &Wavefront, dir: &Path) -> Result<Vec<MtlMaterial>, ObjError> {
+    let mut library: Vec<MtlMaterial> = Vec::new();
+    for lib in wf.material_libs.iter() {
+        let file = dir.join(lib).to_string_lossy().into_owned();
+        let materials = MtlMaterial::parse_file(file).map_err(|e| ObjError {
+            message: format!("{}: {}", lib, e.message),
+            ..e
+        })?;
+        library.extend(materials);
+    }
+    Ok(wf
+        .materials
+        .iter()
+        .map(|name| match library.iter().find(|m| &m.name == name) {
+            Some(m) => m.clone(),
+            None => MtlMaterial { name: name.clone(), ..MtlMaterial::default() },
+        })
+        .collect())
+}
+
+// the image of a map, None if there is no map or it can not be read
+fn read_map(dir: &Path, map: &Option<String>) -> Option<Image> {
+    let bytes = fs::read(dir.join(map.as_ref()?)).ok()?;
+    Image::decode(bytes).ok()
+}
+
+// Kd r [g b], g and b are r if missing
+fn parse_color<'a>(items: impl Iterator<Item = &'a str>) -> Result<Vec3f, String> {
+    let c = parse_floats(items, 1)?;
+    Ok(Vec3f(c[0], *c.get(1).unwrap_or(&c[0]), *c.get(2).unwrap_or(&c[0])))
+}
+
+// the raster images keep the channels in the order of the tga files, blue first
+fn bgr(c: &Vec3f) -> Vec3f {
+    Vec3f(c.2, c.1, c.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIBRARY: &str = "# two materials
+newmtl skin
+Kd 0.8 0.6 0.5
+Ks 0.1
+Ns 250
+map_Kd -s 1 1 1 skin.tga
+map_Bump -bm 0.5 skin_nm.tga
+
+newmtl gold
+Kd 0 0 0
+Ks 1.0 0.8 0.3
+Ke 0 0 0
+d 0.9
+Ni 1.5
+map_Ks gold_spec.tga
+";
+
+    #[test]
+    fn test_parse_library() {
+        let materials = MtlMaterial::parse_string(LIBRARY).unwrap();
+        assert_eq!(materials.len(), 2);
+        let skin = &materials[0];
+        assert_eq!(skin.name, "skin");
+        assert_eq!((skin.diffuse.0, skin.diffuse.1, skin.diffuse.2), (0.8, 0.6, 0.5));
+        assert_eq!((skin.specular.0, skin.specular.1, skin.specular.2), (0.1, 0.1, 0.1));
+        assert_eq!(skin.shininess, 250.0);
+        assert_eq!(skin.diffuse_map.as_deref(), Some("skin.tga"));
+        assert_eq!(skin.bump_map.as_deref(), Some("skin_nm.tga"));
+        assert_eq!(skin.metallic(), 0.0);
+        let gold = &materials[1];
+        assert_eq!((gold.dissolve, gold.ior), (0.9, 1.5));
+        assert_eq!(gold.specular_map.as_deref(), Some("gold_spec.tga"));
+        assert_eq!(gold.metallic(), 1.0);
+        // rougher with a smaller exponent
+        assert!(gold.roughness() > skin.roughness());
+    }
+
+    #[test]
+    fn test_errors() {
+        for (text, line) in [
+            ("Kd 1 1 1\n", 1),
+            ("newmtl a\nKd 1 x 1\n", 2),
+            ("newmtl a\n\nNs\n", 3),
+            ("newmtl a\nmap_Kd\n", 2),
+            ("newmtl\n", 1),
+        ] {
+            assert_eq!(MtlMaterial::parse_string(text).unwrap_err().line, line, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn test_material_groups() {
+        let wf = Wavefront::parse_string(
+            "mtllib a.mtl\nv 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nf 1 2 3\nusemtl skin\nf 1 2 3 4\nusemtl gold\nf 1 2 3\nusemtl skin\nf 1 3 4\n",
+        )
+        .unwrap();
+        assert_eq!(wf.material_libs, vec!["a.mtl"]);
+        assert_eq!(wf.materials, vec!["skin", "gold"]);
+        assert_eq!(wf.face_materials, vec![None, Some(0), Some(0), Some(1), Some(0)]);
+        assert_eq!(wf.face_material(0), None);
+        assert_eq!(wf.face_material(3), Some(1));
+    }
+
+    // a quad of two faces, the second in the `skin` or `gold` group
+    fn write_model(name: &str, group: &str) -> String {
+        let dir = std::env::temp_dir().join(format!("rusterizer_mtl_{}", name));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a.mtl"), LIBRARY).unwrap();
+        let obj = dir.join("model.obj");
+        let model = format!("mtllib a.mtl\nv 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nf 1 2 3\nusemtl {}\nf 1 3 4\n", group);
+        fs::write(&obj, model).unwrap();
+        obj.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn test_raster_surfaces() {
+        let model = MeshObject::from_file(write_model("raster", "skin")).unwrap();
+        assert!(model.surface(0).is_none());
+        let skin = model.surface(1).unwrap();
+        assert_eq!(skin.shininess, 250.0);
+        // the albedo of the pbr shader is Kd, blue first
+        let albedo = skin.material.albedo.value(0.0, 0.0);
+        assert_eq!((albedo.0, albedo.1, albedo.2), (0.5, 0.6, 0.8));
+        // the maps of the library are missing
+        assert!(skin.texture.is_none() && skin.normal_map.is_none());
+        assert!(!model.has_tangent_frames());
+    }
+
+    #[test]
+    fn test_raytracing_materials() {
+        use crate::models::objects::TriangleMesh;
+        use crate::raytracing::{Hittable, Ray};
+
+        let file = write_model("raytracing", "gold");
+        let dir = Path::new(&file).parent().unwrap().to_owned();
+        let wf = Wavefront::parse_file(file).unwrap();
+        let materials: Vec<Arc<dyn Material>> =
+            load_materials(&wf, &dir).unwrap().iter().map(|m| m.raytracing_material(&dir)).collect();
+        let default: Arc<dyn Material> = Arc::new(Lambertian::new(Vec3f(0.5, 0.5, 0.5)));
+        let mesh = TriangleMesh::with_materials(wf, materials.clone(), default.clone());
+        // the first face is below the diagonal of the quad, the second above it
+        let material_at = |x: f32, y: f32| {
+            let ray = Ray::new(Vec3f(x, y, 1.0), Vec3f(0.0, 0.0, -1.0));
+            mesh.hit(0.001, f32::MAX, &ray).unwrap().material.unwrap()
+        };
+        assert!(Arc::ptr_eq(&material_at(0.75, 0.25), &default));
+        assert!(Arc::ptr_eq(&material_at(0.25, 0.75), &materials[0]));
+    }
+}
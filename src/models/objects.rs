@@ -1,12 +1,17 @@
+use std::f32::consts::PI;
 use std::sync::Arc;
+use rand::random;
 
 use crate::{
     la::Vec3f,
     raytracing::Ray,
-    raytracing::{Hittable,Hit},
+    raytracing::{Hittable,Hit,BoundingBox,BvhNode},
     raytracing::materials::Material,
+    utils::{orthonormal_basis,random_to_sphere},
 };
 
+use super::Wavefront;
+
 
 
 pub struct Sphere {
@@ -42,7 +47,229 @@ impl Hittable for Sphere {
         let mut hit = Hit::new(t, p);
         let outward_normal = (p - self.center) / self.radius;
         hit.set_face_normal(ray, outward_normal);
+        (hit.u, hit.v) = sphere_uv(&outward_normal);
+        hit.material = Some(self.material.clone());
+        Some(hit)
+    }
+
+    fn bounding_box(&self) -> Option<BoundingBox> {
+        let r = Vec3f(self.radius, self.radius, self.radius);
+        Some(BoundingBox::new(self.center - r, self.center + r))
+    }
+
+    // uniform over the cone of directions that the sphere covers
+    fn pdf_value(&self, origin: &Vec3f, direction: &Vec3f) -> f32 {
+        let distance_squared = (self.center - *origin).length_squared();
+        let radius_squared = self.radius * self.radius;
+        if distance_squared <= radius_squared
+            || self.hit(0.001, f32::MAX, &Ray::new(*origin, *direction)).is_none()
+        {
+            return 0.0;
+        }
+        let cos_theta_max = (1.0 - radius_squared / distance_squared).sqrt();
+        let solid_angle = 2.0 * PI * (1.0 - cos_theta_max);
+        1.0 / solid_angle
+    }
+
+    fn random_direction(&self, origin: &Vec3f) -> Vec3f {
+        let direction = self.center - *origin;
+        let (u, v, w) = orthonormal_basis(&direction);
+        let d = random_to_sphere(self.radius, direction.length_squared());
+        d.x() * u + d.y() * v + d.z() * w
+    }
+}
+
+// the (u, v) coordinates of a point on the unit sphere
+// u: the angle around the y axis starting at -x, v: the angle from -y up to +y
+fn sphere_uv(p: &Vec3f) -> (f32, f32) {
+    let theta = (-p.y()).clamp(-1.0, 1.0).acos();
+    let phi = (-p.z()).atan2(p.x()) + PI;
+    (phi / (2.0 * PI), theta / PI)
+}
+
+// a parallelogram with corner q and the two edges u and v, e.g. an area light
+pub struct Quad {
+    pub q: Vec3f,
+    pub u: Vec3f,
+    pub v: Vec3f,
+    pub material: Arc<dyn Material>,
+}
+
+impl Quad {
+    pub fn new(q: Vec3f, u: Vec3f, v: Vec3f, material: Arc<dyn Material>) -> Self {
+        Self { q, u, v, material }
+    }
+}
+
+impl Hittable for Quad {
+    fn hit(&self, t_min: f32, t_max: f32, ray: &Ray) -> Option<Hit> {
+        let n = self.u.cross(&self.v);
+        let normal = n.normalize();
+        let denom = normal.dot(&ray.direction);
+        // the ray is parallel to the plane
+        if denom.abs() < 1e-8 {
+            return None;
+        }
+        let t = (normal.dot(&self.q) - normal.dot(&ray.origin)) / denom;
+        if t < t_min || t_max < t {
+            return None;
+        }
+        // the coordinates of the intersection in the (u, v) frame of the plane
+        let p = ray.at(t);
+        let w = n / n.dot(&n);
+        let planar = p - self.q;
+        let alpha = w.dot(&planar.cross(&self.v));
+        let beta = w.dot(&self.u.cross(&planar));
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return None;
+        }
+        let mut hit = Hit::new(t, p);
+        hit.set_face_normal(ray, normal);
+        hit.u = alpha;
+        hit.v = beta;
         hit.material = Some(self.material.clone());
         Some(hit)
     }
-}
\ No newline at end of file
+
+    fn bounding_box(&self) -> Option<BoundingBox> {
+        let diagonal1 = BoundingBox::new(self.q, self.q + self.u + self.v);
+        let diagonal2 = BoundingBox::new(self.q + self.u, self.q + self.v);
+        Some(diagonal1.surrounding(&diagonal2).padded(1e-4))
+    }
+
+    // uniform over the area of the quad, converted to solid angle
+    fn pdf_value(&self, origin: &Vec3f, direction: &Vec3f) -> f32 {
+        let ray = Ray::new(*origin, *direction);
+        match self.hit(0.001, f32::MAX, &ray) {
+            Some(rec) => {
+                let area = self.u.cross(&self.v).length();
+                let cosine = ray.direction.dot(&rec.normal.unwrap()).abs();
+                if cosine < 1e-8 {
+                    return 0.0;
+                }
+                rec.t * rec.t / (cosine * area)
+            }
+            None => 0.0,
+        }
+    }
+
+    fn random_direction(&self, origin: &Vec3f) -> Vec3f {
+        self.q + random::<f32>() * self.u + random::<f32>() * self.v - *origin
+    }
+}
+
+// a single face of a wavefront mesh, the geometry is shared with the other faces
+pub struct Triangle {
+    pub mesh: Arc<Wavefront>,
+    pub face: usize,
+    pub material: Arc<dyn Material>,
+}
+
+impl Triangle {
+    pub fn new(mesh: Arc<Wavefront>, face: usize, material: Arc<dyn Material>) -> Self {
+        Self { mesh, face, material }
+    }
+}
+
+impl Hittable for Triangle {
+    // Möller–Trumbore ray/triangle intersection
+    fn hit(&self, t_min: f32, t_max: f32, ray: &Ray) -> Option<Hit> {
+        const EPSILON: f32 = 1e-8;
+        let v0 = self.mesh.vertex(self.face, 0);
+        let v1 = self.mesh.vertex(self.face, 1);
+        let v2 = self.mesh.vertex(self.face, 2);
+        let edge1 = v1 - v0;
+        let edge2 = v2 - v0;
+        let p = ray.direction.cross(&edge2);
+        let det = edge1.dot(&p);
+        // the ray is parallel to the triangle
+        if det.abs() < EPSILON {
+            return None;
+        }
+        let inv_det = 1.0 / det;
+        let s = ray.origin - v0;
+        let u = s.dot(&p) * inv_det;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+        let q = s.cross(&edge1);
+        let v = ray.direction.dot(&q) * inv_det;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+        let t = edge2.dot(&q) * inv_det;
+        if t < t_min || t_max < t {
+            return None;
+        }
+
+        let mut hit = Hit::new(t, ray.at(t));
+        // the front face is decided by the geometry, the shading normal only follows it
+        hit.set_face_normal(ray, edge1.cross(&edge2).normalize());
+        let normals = [
+            self.mesh.vertex_normal(self.face, 0),
+            self.mesh.vertex_normal(self.face, 1),
+            self.mesh.vertex_normal(self.face, 2),
+        ];
+        if let [Some(n0), Some(n1), Some(n2)] = normals {
+            let normal = ((1.0 - u - v) * n0 + u * n1 + v * n2).normalize();
+            hit.normal = Some(if hit.front_face.unwrap() { normal } else { -normal });
+        }
+        // the texture coordinates of the model, or the barycentric ones without `vt` data
+        let uvs = [
+            self.mesh.texture_coords(self.face, 0),
+            self.mesh.texture_coords(self.face, 1),
+            self.mesh.texture_coords(self.face, 2),
+        ];
+        (hit.u, hit.v) = match uvs {
+            [Some(t0), Some(t1), Some(t2)] => (
+                (1.0 - u - v) * t0[0] + u * t1[0] + v * t2[0],
+                (1.0 - u - v) * t0[1] + u * t1[1] + v * t2[1],
+            ),
+            _ => (u, v),
+        };
+        hit.material = Some(self.material.clone());
+        Some(hit)
+    }
+
+    fn bounding_box(&self) -> Option<BoundingBox> {
+        let v0 = self.mesh.vertex(self.face, 0);
+        let v1 = self.mesh.vertex(self.face, 1);
+        let v2 = self.mesh.vertex(self.face, 2);
+        Some(BoundingBox::new(v0.min(&v1).min(&v2), v0.max(&v1).max(&v2)).padded(1e-4))
+    }
+}
+
+// a triangle mesh loaded from a wavefront file, every face shares the same material
+// the faces are kept in a bvh, so the mesh can be hit in logarithmic time
+pub struct TriangleMesh {
+    pub triangles: BvhNode,
+}
+
+impl TriangleMesh {
+    pub fn new(mesh: Wavefront, material: Arc<dyn Material>) -> Self {
+        TriangleMesh::with_materials(mesh, Vec::new(), material)
+    }
+
+    // materials: in the order of `Wavefront::materials`, the faces without one use `default`
+    pub fn with_materials(mesh: Wavefront, materials: Vec<Arc<dyn Material>>, default: Arc<dyn Material>) -> Self {
+        assert!(mesh.num_faces() > 0, "can not build a mesh without faces");
+        let mesh = Arc::new(mesh);
+        let triangles = (0..mesh.num_faces())
+            .map(|face| {
+                let material = mesh.face_material(face).and_then(|m| materials.get(m)).unwrap_or(&default);
+                Box::new(Triangle::new(mesh.clone(), face, material.clone())) as Box<dyn Hittable>
+            })
+            .collect();
+        Self { triangles: BvhNode::new(triangles) }
+    }
+}
+
+impl Hittable for TriangleMesh {
+    fn hit(&self, t_min: f32, t_max: f32, ray: &Ray) -> Option<Hit> {
+        self.triangles.hit(t_min, t_max, ray)
+    }
+
+    fn bounding_box(&self) -> Option<BoundingBox> {
+        self.triangles.bounding_box()
+    }
+}
@@ -0,0 +1,69 @@
+use crate::{
+    la::Vec3f,
+    tga::{Filter, Image, Sampler, WrapMode},
+};
+
+// a parameter of the physically based material, constant or looked up in a texture at (u,v)
+// values are in [0,1] per channel
+pub enum PbrParam {
+    Constant(Vec3f),
+    Texture(Image),
+}
+
+impl PbrParam {
+    pub fn value(&self, u: f32, v: f32) -> Vec3f {
+        match self {
+            PbrParam::Constant(c) => *c,
+            PbrParam::Texture(img) => {
+                let c = img.sample(&Sampler::new(WrapMode::Repeat, Filter::Bilinear), u, v);
+                Vec3f(c.0 as f32, c.1 as f32, c.2 as f32).mulf(1.0 / 255.0)
+            }
+        }
+    }
+}
+
+// metallic/roughness material of a mesh for the pbr shader
+// albedo is in srgb like the textures, metallic, roughness and specular are read from the first channel
+pub struct PbrMaterial {
+    pub albedo: PbrParam,
+    pub metallic: PbrParam,
+    pub roughness: PbrParam,
+    // the reflectance of the dielectrics, 0.5 is the usual 4%
+    pub specular: PbrParam,
+}
+
+impl Default for PbrMaterial {
+    fn default() -> Self {
+        PbrMaterial {
+            albedo: PbrParam::Constant(Vec3f(0.6, 0.6, 0.6)),
+            metallic: PbrParam::Constant(Vec3f(0.0, 0.0, 0.0)),
+            roughness: PbrParam::Constant(Vec3f(0.5, 0.5, 0.5)),
+            specular: PbrParam::Constant(Vec3f(0.5, 0.5, 0.5)),
+        }
+    }
+}
+
+impl PbrMaterial {
+    pub fn new(albedo: PbrParam, metallic: PbrParam, roughness: PbrParam) -> Self {
+        PbrMaterial { albedo, metallic, roughness, ..PbrMaterial::default() }
+    }
+
+    // the albedo in linear color
+    pub fn albedo(&self, u: f32, v: f32) -> Vec3f {
+        let c = self.albedo.value(u, v);
+        Vec3f(c.0.powf(2.2), c.1.powf(2.2), c.2.powf(2.2))
+    }
+
+    pub fn metallic(&self, u: f32, v: f32) -> f32 {
+        self.metallic.value(u, v).0.clamp(0.0, 1.0)
+    }
+
+    pub fn roughness(&self, u: f32, v: f32) -> f32 {
+        self.roughness.value(u, v).0.clamp(0.0, 1.0)
+    }
+
+    // the reflectance at normal incidence of the dielectrics
+    pub fn f0(&self, u: f32, v: f32) -> f32 {
+        0.08 * self.specular.value(u, v).0.clamp(0.0, 1.0)
+    }
+}
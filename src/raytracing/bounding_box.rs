@@ -0,0 +1,82 @@
+use crate::la::Vec3f;
+
+use super::Ray;
+
+// axis-aligned bounding box
+#[derive(Clone, Copy, Debug)]
+pub struct BoundingBox {
+    pub minimum: Vec3f,
+    pub maximum: Vec3f,
+}
+
+impl BoundingBox {
+    pub fn new(a: Vec3f, b: Vec3f) -> Self {
+        Self {
+            minimum: a.min(&b),
+            maximum: a.max(&b),
+        }
+    }
+
+    // the smallest box that contains both boxes
+    pub fn surrounding(&self, other: &BoundingBox) -> BoundingBox {
+        BoundingBox {
+            minimum: self.minimum.min(&other.minimum),
+            maximum: self.maximum.max(&other.maximum),
+        }
+    }
+
+    // grow the axes thinner than delta, so that flat objects (e.g. triangles in a plane) still get hit
+    pub fn padded(&self, delta: f32) -> BoundingBox {
+        let mut minimum = self.minimum;
+        let mut maximum = self.maximum;
+        let half = delta / 2.0;
+        if maximum.0 - minimum.0 < delta {
+            minimum.0 -= half;
+            maximum.0 += half;
+        }
+        if maximum.1 - minimum.1 < delta {
+            minimum.1 -= half;
+            maximum.1 += half;
+        }
+        if maximum.2 - minimum.2 < delta {
+            minimum.2 -= half;
+            maximum.2 += half;
+        }
+        BoundingBox { minimum, maximum }
+    }
+
+    pub fn centroid(&self) -> Vec3f {
+        (self.minimum + self.maximum) * 0.5
+    }
+
+    // the index of the axis along which the box is the largest
+    pub fn longest_axis(&self) -> usize {
+        let extent = self.maximum - self.minimum;
+        if extent.0 > extent.1 && extent.0 > extent.2 {
+            0
+        } else if extent.1 > extent.2 {
+            1
+        } else {
+            2
+        }
+    }
+
+    // slab test: does the ray pass through the box within (t_min, t_max)
+    pub fn hit(&self, mut t_min: f32, mut t_max: f32, ray: &Ray) -> bool {
+        for axis in 0..3 {
+            let inv_d = 1.0 / ray.direction[axis];
+            let mut t0 = (self.minimum[axis] - ray.origin[axis]) * inv_d;
+            let mut t1 = (self.maximum[axis] - ray.origin[axis]) * inv_d;
+            if inv_d < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            // NaN (0 * inf) must not shrink the interval, so compare explicitly
+            t_min = if t0 > t_min { t0 } else { t_min };
+            t_max = if t1 < t_max { t1 } else { t_max };
+            if t_max < t_min {
+                return false;
+            }
+        }
+        true
+    }
+}
@@ -0,0 +1,165 @@
+use super::{
+    BoundingBox,
+    Hit,
+    Hittable,
+    Ray,
+};
+
+// bounding volume hierarchy, built once by splitting the objects at the median
+// of their centroids along the longest axis
+pub struct BvhNode {
+    left: Box<dyn Hittable>,
+    right: Option<Box<dyn Hittable>>,
+    bbox: BoundingBox,
+}
+
+impl BvhNode {
+    // every object must have a bounding box and the list must not be empty
+    pub fn new(objects: Vec<Box<dyn Hittable>>) -> Self {
+        assert!(!objects.is_empty(), "can not build a bvh without objects");
+        let mut objects: Vec<(BoundingBox, Box<dyn Hittable>)> = objects
+            .into_iter()
+            .map(|object| {
+                let bbox = object
+                    .bounding_box()
+                    .expect("the objects in a bvh must have a bounding box");
+                (bbox, object)
+            })
+            .collect();
+        Self::build(&mut objects)
+    }
+
+    fn build(objects: &mut Vec<(BoundingBox, Box<dyn Hittable>)>) -> Self {
+        let bbox = objects
+            .iter()
+            .skip(1)
+            .fold(objects[0].0, |bbox, (b, _)| bbox.surrounding(b));
+        match objects.len() {
+            1 => {
+                let (_, left) = objects.pop().unwrap();
+                Self { left, right: None, bbox }
+            }
+            2 => {
+                let (_, right) = objects.pop().unwrap();
+                let (_, left) = objects.pop().unwrap();
+                Self { left, right: Some(right), bbox }
+            }
+            n => {
+                let centroids = objects
+                    .iter()
+                    .skip(1)
+                    .fold(BoundingBox::new(objects[0].0.centroid(), objects[0].0.centroid()), |bbox, (b, _)| {
+                        bbox.surrounding(&BoundingBox::new(b.centroid(), b.centroid()))
+                    });
+                let axis = centroids.longest_axis();
+                objects.sort_by(|(a, _), (b, _)| {
+                    a.centroid()[axis]
+                        .partial_cmp(&b.centroid()[axis])
+                        .unwrap_or(std::cmp::Ordering::Equal)
+                });
+                let mut right_objects = objects.split_off(n / 2);
+                let left = Self::build(objects);
+                let right = Self::build(&mut right_objects);
+                Self {
+                    left: Box::new(left),
+                    right: Some(Box::new(right)),
+                    bbox,
+                }
+            }
+        }
+    }
+}
+
+impl Hittable for BvhNode {
+    fn hit(&self, t_min: f32, t_max: f32, r: &Ray) -> Option<Hit> {
+        if !self.bbox.hit(t_min, t_max, r) {
+            return None;
+        }
+        let left = self.left.hit(t_min, t_max, r);
+        let closest_so_far = left.as_ref().map_or(t_max, |rec| rec.t);
+        let right = self
+            .right
+            .as_ref()
+            .and_then(|right| right.hit(t_min, closest_so_far, r));
+        right.or(left)
+    }
+
+    fn bounding_box(&self) -> Option<BoundingBox> {
+        Some(self.bbox)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        la::Vec3f,
+        models::objects::Sphere,
+        raytracing::materials::Lambertian,
+        scene::{RayTracingScene, SceneTrait},
+    };
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use std::sync::Arc;
+
+    fn spheres(seed: u64) -> Vec<Box<dyn Hittable>> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let material = Arc::new(Lambertian::new(Vec3f(0.5, 0.5, 0.5)));
+        (0..500)
+            .map(|_| {
+                let center = Vec3f(
+                    rng.gen_range(-10.0, 10.0),
+                    rng.gen_range(-10.0, 10.0),
+                    rng.gen_range(-10.0, 10.0),
+                );
+                Box::new(Sphere {
+                    center,
+                    radius: rng.gen_range(0.05, 1.0),
+                    material: material.clone(),
+                }) as Box<dyn Hittable>
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_bvh_same_hits_as_brute_force() {
+        let mut brute_force = RayTracingScene::new();
+        let mut with_bvh = RayTracingScene::new();
+        for object in spheres(42) {
+            brute_force.add(object);
+        }
+        for object in spheres(42) {
+            with_bvh.add(object);
+        }
+        with_bvh.build_bvh();
+
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut hits = 0;
+        for _ in 0..2000 {
+            let origin = Vec3f(
+                rng.gen_range(-15.0, 15.0),
+                rng.gen_range(-15.0, 15.0),
+                rng.gen_range(-15.0, 15.0),
+            );
+            let direction = Vec3f(
+                rng.gen_range(-1.0, 1.0),
+                rng.gen_range(-1.0, 1.0),
+                rng.gen_range(-1.0, 1.0),
+            );
+            let ray = Ray::new(origin, direction);
+            let expected = brute_force.hit(0.001, f32::MAX, &ray);
+            let actual = with_bvh.hit(0.001, f32::MAX, &ray);
+            match (expected, actual) {
+                (Some(e), Some(a)) => {
+                    hits += 1;
+                    assert_eq!(e.t, a.t);
+                    assert_eq!(e.place.0, a.place.0);
+                    assert_eq!(e.place.1, a.place.1);
+                    assert_eq!(e.place.2, a.place.2);
+                }
+                (None, None) => {}
+                (e, a) => panic!("brute force hit: {}, bvh hit: {}", e.is_some(), a.is_some()),
+            }
+        }
+        assert!(hits > 0);
+    }
+}
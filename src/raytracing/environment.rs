@@ -0,0 +1,46 @@
+use std::f32::consts::PI;
+
+use crate::{
+    hdr::HdrImage,
+    la::Vec3f,
+};
+
+// the light that comes from far away, seen by the rays that miss every object of the scene
+pub enum Environment {
+    // the same color in every direction, black for closed interiors
+    Solid(Vec3f),
+    // blend from `bottom` (looking down) to `top` (looking up)
+    Gradient { bottom: Vec3f, top: Vec3f },
+    // an equirectangular (latitude-longitude) map, the center of the image looks towards -z
+    Map(HdrImage),
+}
+
+impl Default for Environment {
+    // the white to sky blue gradient
+    fn default() -> Self {
+        Environment::Gradient {
+            bottom: Vec3f(1.0, 1.0, 1.0),
+            top: Vec3f(0.5, 0.7, 1.0),
+        }
+    }
+}
+
+impl Environment {
+    pub fn color(&self, direction: &Vec3f) -> Vec3f {
+        let unit_direction = direction.normalize();
+        match self {
+            Environment::Solid(color) => *color,
+            Environment::Gradient { bottom, top } => {
+                let t = 0.5 * (unit_direction.y() + 1.0);
+                (1.0 - t) * *bottom + t * *top
+            }
+            Environment::Map(image) => {
+                let u = 0.5 + unit_direction.x().atan2(-unit_direction.z()) / (2.0 * PI);
+                let v = unit_direction.y().clamp(-1.0, 1.0).acos() / PI;
+                let x = ((u * image.width as f32) as i32).clamp(0, image.width - 1);
+                let y = ((v * image.height as f32) as i32).clamp(0, image.height - 1);
+                image.pixel_at(x, y)
+            }
+        }
+    }
+}
@@ -4,6 +4,7 @@ use crate::{
 
 use super::{
     materials::Material,
+    BoundingBox,
     Ray,
 };
 use std::sync::Arc;
@@ -15,6 +16,9 @@ pub struct Hit {
     pub normal: Option<Vec3f>,
     pub front_face: Option<bool>,
     pub material: Option<Arc<dyn Material>>,
+    // the surface coordinates of the hit, for the textures
+    pub u: f32,
+    pub v: f32,
 }
 
 impl Default for Hit {
@@ -24,13 +28,15 @@ impl Default for Hit {
             place: Vec3f(0f32, 0f32, 0f32), 
             normal: None, 
             front_face: None, 
-            material: None
+            material: None,
+            u: 0f32,
+            v: 0f32,
         }
     }
 }
 impl Hit {
     pub fn new(t: f32, place: Vec3f) -> Self {
-        Self { t, place, normal: None, front_face: None, material: None }
+        Self { t, place, normal: None, front_face: None, material: None, u: 0f32, v: 0f32 }
     }
     pub fn set_face_normal(&mut self, ray:&Ray, outward_normal: Vec3f) {
         let front_face = ray.direction.dot(&outward_normal) < 0.0;
@@ -46,5 +52,35 @@ impl Hit {
 
 pub trait Hittable : Send + Sync {
     fn hit(&self, t_min: f32, t_max: f32, r: &Ray) -> Option<Hit>;
+    // None if the object is unbounded
+    fn bounding_box(&self) -> Option<BoundingBox>;
+    // the probability density (over solid angle) that `random_direction` picks `direction` from `origin`,
+    // only objects that can be sampled as lights implement it
+    fn pdf_value(&self, _origin: &Vec3f, _direction: &Vec3f) -> f32 {
+        0.0
+    }
+    // a random direction from `origin` towards the object
+    fn random_direction(&self, _origin: &Vec3f) -> Vec3f {
+        Vec3f(1.0, 0.0, 0.0)
+    }
+}
+
+// shared objects, e.g. a light that is both in the scene and in its light list
+impl<T: Hittable + ?Sized> Hittable for Arc<T> {
+    fn hit(&self, t_min: f32, t_max: f32, r: &Ray) -> Option<Hit> {
+        (**self).hit(t_min, t_max, r)
+    }
+
+    fn bounding_box(&self) -> Option<BoundingBox> {
+        (**self).bounding_box()
+    }
+
+    fn pdf_value(&self, origin: &Vec3f, direction: &Vec3f) -> f32 {
+        (**self).pdf_value(origin, direction)
+    }
+
+    fn random_direction(&self, origin: &Vec3f) -> Vec3f {
+        (**self).random_direction(origin)
+    }
 }
 
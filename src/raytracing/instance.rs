@@ -0,0 +1,66 @@
+use std::sync::Arc;
+
+use crate::{
+    la::{Matrix, MatrixI, Vec3f},
+    transform::{transform_point, transform_vector},
+};
+
+use super::{
+    BoundingBox,
+    Hit,
+    Hittable,
+    Ray,
+};
+
+// an object placed in the world by an affine transform, the geometry is shared
+// so one loaded mesh can be placed many times with different poses
+pub struct Instance {
+    pub object: Arc<dyn Hittable>,
+    // object space to world space
+    pub transform: Matrix<4, 4>,
+    // world space to object space
+    pub inverse: Matrix<4, 4>,
+}
+
+impl Instance {
+    pub fn new(object: Arc<dyn Hittable>, transform: Matrix<4, 4>) -> Self {
+        Self {
+            object,
+            transform,
+            inverse: transform.inverse(),
+        }
+    }
+}
+
+impl Hittable for Instance {
+    fn hit(&self, t_min: f32, t_max: f32, r: &Ray) -> Option<Hit> {
+        // the direction is not normalized, so t is the same in both spaces
+        let object_ray = Ray {
+            origin: transform_point(&self.inverse, &r.origin),
+            direction: transform_vector(&self.inverse, &r.direction),
+        };
+        let mut hit = self.object.hit(t_min, t_max, &object_ray)?;
+        hit.place = transform_point(&self.transform, &hit.place);
+        // normals are transformed by the inverse transpose
+        hit.normal = hit
+            .normal
+            .map(|normal| transform_vector(&self.inverse.transpose(), &normal).normalize());
+        Some(hit)
+    }
+
+    fn bounding_box(&self) -> Option<BoundingBox> {
+        let bbox = self.object.bounding_box()?;
+        let mut corners = (0..8).map(|i| {
+            let corner = Vec3f(
+                if i & 1 == 0 { bbox.minimum.0 } else { bbox.maximum.0 },
+                if i & 2 == 0 { bbox.minimum.1 } else { bbox.maximum.1 },
+                if i & 4 == 0 { bbox.minimum.2 } else { bbox.maximum.2 },
+            );
+            transform_point(&self.transform, &corner)
+        });
+        let first = corners.next().unwrap();
+        Some(corners.fold(BoundingBox::new(first, first), |bbox, corner| {
+            bbox.surrounding(&BoundingBox::new(corner, corner))
+        }))
+    }
+}
@@ -3,62 +3,98 @@ use crate::{
     utils::{random_in_unit_sphere,random_unit_vector,reflect,other_refract},
 };
 use rand::{random};
+use std::f32::consts::PI;
 use super::{
     hit::Hit,
     ray::Ray,
+    textures::{SolidColor,Texture},
 };
+use std::sync::Arc;
 
 
 #[derive(Clone, Copy, Debug)]
 pub struct Scatter {
     pub attenuation: Vec3f,
     pub ray: Ray,
+    // the probability density of the scattered direction, None for specular (delta) scattering
+    // which can not be combined with light sampling
+    pub pdf: Option<f32>,
 }
 
 pub trait Material: Send + Sync {
     fn scatter(&self, r_in: &Ray, rec: &Hit) -> Option<Scatter>;
+    // the light that the material gives off by itself, black for the non-emissive materials
+    fn emitted(&self, _rec: &Hit) -> Vec3f {
+        Vec3f::zeroed()
+    }
+    // bsdf times cosine for the light coming from `direction`, only needed by non specular materials
+    fn eval(&self, _r_in: &Ray, _rec: &Hit, _direction: &Vec3f) -> Vec3f {
+        Vec3f::zeroed()
+    }
+    // the probability density that `scatter` picks `direction`
+    fn pdf(&self, _r_in: &Ray, _rec: &Hit, _direction: &Vec3f) -> f32 {
+        0.0
+    }
 }
 
-#[derive(Debug)]
 pub struct Lambertian {
     // reflect ratio
-    pub albedo: Vec3f,
+    pub albedo: Arc<dyn Texture>,
 }
 
 impl Lambertian {
     pub fn new(color: Vec3f) -> Lambertian {
-        Lambertian { albedo: color }
+        Lambertian::with_texture(Arc::new(SolidColor::new(color)))
+    }
+
+    pub fn with_texture(texture: Arc<dyn Texture>) -> Lambertian {
+        Lambertian { albedo: texture }
     }
 }
 
 impl Material for Lambertian {
-    fn scatter(&self, _r_in: &Ray, rec: &Hit) -> Option<Scatter> {
+    fn scatter(&self, r_in: &Ray, rec: &Hit) -> Option<Scatter> {
         let normal = rec.normal.unwrap();
         let mut scatter_direction = normal + random_unit_vector();
         if scatter_direction.near_zero() {
             scatter_direction = normal;
         }
+        // normal + random unit vector is cosine weighted
+        let pdf = self.pdf(r_in, rec, &scatter_direction);
         Some(Scatter {
-            attenuation: self.albedo,
+            attenuation: self.albedo.value(rec.u, rec.v, &rec.place),
             ray: Ray {
                 origin: rec.place,
                 direction: scatter_direction,
             },
+            pdf: Some(pdf),
         })
     }
+
+    fn eval(&self, r_in: &Ray, rec: &Hit, direction: &Vec3f) -> Vec3f {
+        self.albedo.value(rec.u, rec.v, &rec.place) * self.pdf(r_in, rec, direction)
+    }
+
+    fn pdf(&self, _r_in: &Ray, rec: &Hit, direction: &Vec3f) -> f32 {
+        let cosine = rec.normal.unwrap().dot(&direction.normalize());
+        if cosine > 0.0 { cosine / PI } else { 0.0 }
+    }
 }
 
-#[derive(Debug)]
 pub struct Metal {
     // reflect ratio
-    pub albedo: Vec3f,
+    pub albedo: Arc<dyn Texture>,
     pub fuzz: f32,
 }
 
 impl Metal {
     pub fn new(color: Vec3f, f: f32) -> Metal {
+        Metal::with_texture(Arc::new(SolidColor::new(color)), f)
+    }
+
+    pub fn with_texture(texture: Arc<dyn Texture>, f: f32) -> Metal {
         Metal {
-            albedo: color,
+            albedo: texture,
             fuzz: if f < 1.0 { f } else { 1.0 },
         }
     }
@@ -74,8 +110,9 @@ impl Material for Metal {
         let same_direction = scattered.direction.dot(&rec.normal.unwrap()) > 0.0;
         if same_direction {
             Some(Scatter {
-                attenuation: self.albedo,
+                attenuation: self.albedo.value(rec.u, rec.v, &rec.place),
                 ray: scattered,
+                pdf: None,
             })
         } else {
             None
@@ -128,6 +165,7 @@ impl Material for Dielectric {
                 origin: rec.place,
                 direction,
             },
+            pdf: None,
         })
     }
 }
@@ -138,4 +176,28 @@ fn schlick(cosine: f32, index: f32) -> f32 {
     let r0 = (1.0 - index) / (1.0 + index);
     let r0 = r0 * r0;
     r0 + (1.0 - r0) * ((1.0 - cosine).powi(5))
-}
\ No newline at end of file
+}
+
+/// 发光体
+/// 只发光不反射的材料，例如灯
+#[derive(Debug)]
+pub struct DiffuseLight {
+    // emitted radiance, can be greater than 1
+    pub emit: Vec3f,
+}
+
+impl DiffuseLight {
+    pub fn new(color: Vec3f) -> DiffuseLight {
+        DiffuseLight { emit: color }
+    }
+}
+
+impl Material for DiffuseLight {
+    fn scatter(&self, _r_in: &Ray, _rec: &Hit) -> Option<Scatter> {
+        None
+    }
+
+    fn emitted(&self, _rec: &Hit) -> Vec3f {
+        self.emit
+    }
+}
@@ -1,10 +1,19 @@
 mod exposure_camera;
 mod hit;
 mod ray;
+mod bounding_box;
+mod bvh;
+mod environment;
+mod instance;
 pub mod materials;
+pub mod textures;
 
 
 
 pub use ray::Ray;
 pub use hit::{Hit,Hittable};
+pub use bounding_box::BoundingBox;
+pub use bvh::BvhNode;
+pub use environment::Environment;
+pub use instance::Instance;
 pub use exposure_camera::{Exposure,ExposureCamera};
\ No newline at end of file
@@ -0,0 +1,249 @@
+use crate::{
+    la::Vec3f,
+    tga::{Filter, Image, Sampler, WrapMode},
+};
+use rand::{random, seq::SliceRandom};
+use std::sync::Arc;
+
+// the color of a surface at the (u, v) coordinates of the hit or at the hit point p
+pub trait Texture: Send + Sync {
+    fn value(&self, u: f32, v: f32, p: &Vec3f) -> Vec3f;
+}
+
+#[derive(Debug)]
+pub struct SolidColor {
+    pub color: Vec3f,
+}
+
+impl SolidColor {
+    pub fn new(color: Vec3f) -> SolidColor {
+        SolidColor { color }
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _u: f32, _v: f32, _p: &Vec3f) -> Vec3f {
+        self.color
+    }
+}
+
+// a 3d checker board, the cells are `scale` wide
+pub struct CheckerTexture {
+    pub even: Arc<dyn Texture>,
+    pub odd: Arc<dyn Texture>,
+    pub scale: f32,
+}
+
+impl CheckerTexture {
+    pub fn new(even: Arc<dyn Texture>, odd: Arc<dyn Texture>, scale: f32) -> CheckerTexture {
+        CheckerTexture { even, odd, scale }
+    }
+
+    pub fn from_colors(even: Vec3f, odd: Vec3f, scale: f32) -> CheckerTexture {
+        CheckerTexture::new(Arc::new(SolidColor::new(even)), Arc::new(SolidColor::new(odd)), scale)
+    }
+}
+
+impl Texture for CheckerTexture {
+    fn value(&self, u: f32, v: f32, p: &Vec3f) -> Vec3f {
+        let cell = |c: f32| (c / self.scale).floor() as i32;
+        if (cell(p.x()) + cell(p.y()) + cell(p.z())) % 2 == 0 {
+            self.even.value(u, v, p)
+        } else {
+            self.odd.value(u, v, p)
+        }
+    }
+}
+
+const POINT_COUNT: usize = 256;
+
+// gradient noise with random unit vectors on the lattice points
+pub struct Perlin {
+    vectors: Vec<Vec3f>,
+    perm_x: Vec<usize>,
+    perm_y: Vec<usize>,
+    perm_z: Vec<usize>,
+}
+
+impl Perlin {
+    pub fn new() -> Perlin {
+        let vectors = (0..POINT_COUNT)
+            .map(|_| (2.0 * random::<Vec3f>() - Vec3f(1.0, 1.0, 1.0)).normalize())
+            .collect();
+        let permutation = || {
+            let mut p: Vec<usize> = (0..POINT_COUNT).collect();
+            p.shuffle(&mut rand::thread_rng());
+            p
+        };
+        Perlin {
+            vectors,
+            perm_x: permutation(),
+            perm_y: permutation(),
+            perm_z: permutation(),
+        }
+    }
+
+    // noise in [-1, 1]
+    pub fn noise(&self, p: &Vec3f) -> f32 {
+        let u = p.x() - p.x().floor();
+        let v = p.y() - p.y().floor();
+        let w = p.z() - p.z().floor();
+        let i = p.x().floor() as i32;
+        let j = p.y().floor() as i32;
+        let k = p.z().floor() as i32;
+
+        // hermitian smoothing
+        let uu = u * u * (3.0 - 2.0 * u);
+        let vv = v * v * (3.0 - 2.0 * v);
+        let ww = w * w * (3.0 - 2.0 * w);
+
+        let mut accum = 0.0;
+        for di in 0..2 {
+            for dj in 0..2 {
+                for dk in 0..2 {
+                    let index = self.perm_x[((i + di) & 255) as usize]
+                        ^ self.perm_y[((j + dj) & 255) as usize]
+                        ^ self.perm_z[((k + dk) & 255) as usize];
+                    let weight = Vec3f(u - di as f32, v - dj as f32, w - dk as f32);
+                    let (fi, fj, fk) = (di as f32, dj as f32, dk as f32);
+                    accum += (fi * uu + (1.0 - fi) * (1.0 - uu))
+                        * (fj * vv + (1.0 - fj) * (1.0 - vv))
+                        * (fk * ww + (1.0 - fk) * (1.0 - ww))
+                        * self.vectors[index].dot(&weight);
+                }
+            }
+        }
+        accum
+    }
+
+    // sum of `depth` octaves of noise
+    pub fn turbulence(&self, p: &Vec3f, depth: usize) -> f32 {
+        let mut accum = 0.0;
+        let mut temp_p = *p;
+        let mut weight = 1.0;
+        for _ in 0..depth {
+            accum += weight * self.noise(&temp_p);
+            weight *= 0.5;
+            temp_p = temp_p * 2.0;
+        }
+        accum.abs()
+    }
+}
+
+impl Default for Perlin {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// marble like perlin noise
+pub struct NoiseTexture {
+    pub noise: Perlin,
+    pub scale: f32,
+}
+
+impl NoiseTexture {
+    pub fn new(scale: f32) -> NoiseTexture {
+        NoiseTexture {
+            noise: Perlin::new(),
+            scale,
+        }
+    }
+}
+
+impl Texture for NoiseTexture {
+    fn value(&self, _u: f32, _v: f32, p: &Vec3f) -> Vec3f {
+        let phase = self.scale * p.z() + 10.0 * self.noise.turbulence(p, 7);
+        Vec3f(0.5, 0.5, 0.5) * (1.0 + phase.sin())
+    }
+}
+
+// looked up the same way as `MeshObject::texture` in the rasterizer
+pub struct ImageTexture {
+    pub image: Image,
+    pub sampler: Sampler,
+}
+
+impl ImageTexture {
+    pub fn new(image: Image) -> ImageTexture {
+        ImageTexture {
+            image,
+            sampler: Sampler::new(WrapMode::Clamp, Filter::Bilinear),
+        }
+    }
+
+    pub fn with_sampler(image: Image, sampler: Sampler) -> ImageTexture {
+        ImageTexture { image, sampler }
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f32, v: f32, _p: &Vec3f) -> Vec3f {
+        if self.image.width <= 0 || self.image.height <= 0 {
+            // debugging aid for a missing texture
+            return Vec3f(0.0, 1.0, 1.0);
+        }
+        // there are no ray differentials, so no mip level to pick
+        let color = self.image.sample(&self.sampler, u, v);
+        // the images keep the channels in the order of the tga files, blue first
+        Vec3f(color.2 as f32, color.1 as f32, color.0 as f32) / 255.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tga::Color;
+
+    #[test]
+    fn test_checker() {
+        let checker = CheckerTexture::from_colors(Vec3f(1.0, 1.0, 1.0), Vec3f(0.0, 0.0, 0.0), 0.5);
+        let at = |x: f32, y: f32, z: f32| checker.value(0.0, 0.0, &Vec3f(x, y, z)).x();
+        assert_eq!(at(0.1, 0.1, 0.1), 1.0);
+        // the next cell along every axis is odd, and the one after is even again
+        assert_eq!(at(0.6, 0.1, 0.1), 0.0);
+        assert_eq!(at(0.1, 0.6, 0.1), 0.0);
+        assert_eq!(at(0.1, 0.1, 0.6), 0.0);
+        assert_eq!(at(0.6, 0.6, 0.1), 1.0);
+        assert_eq!(at(1.1, 0.1, 0.1), 1.0);
+    }
+
+    #[test]
+    fn test_noise() {
+        let texture = NoiseTexture::new(4.0);
+        let values: Vec<f32> = (0..100)
+            .map(|i| texture.value(0.0, 0.0, &Vec3f(i as f32 * 0.37, 1.3, i as f32 * 0.11)).x())
+            .collect();
+        // gray, in [0, 1] and not constant
+        assert!(values.iter().all(|v| (0.0..=1.0).contains(v)));
+        assert!(values.iter().any(|&v| (v - values[0]).abs() > 0.1));
+        let p = Vec3f(0.5, 0.25, 2.0);
+        let c = texture.value(0.0, 0.0, &p);
+        assert_eq!((c.x(), c.x()), (c.y(), c.z()));
+        assert_eq!(c.x(), texture.value(0.7, 0.7, &p).x());
+        // the noise is smooth
+        let near = texture.noise.noise(&Vec3f(0.5, 0.25, 2.001));
+        assert!((texture.noise.noise(&p) - near).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_image() {
+        // blue first, row 0 at the bottom
+        let mut image = Image::new(2, 2);
+        image.set_pixel(0, 0, Color(0, 0, 255));
+        image.set_pixel(1, 0, Color(0, 255, 0));
+        image.set_pixel(0, 1, Color(255, 0, 0));
+        image.set_pixel(1, 1, Color(255, 255, 255));
+        let texture = ImageTexture::with_sampler(image, Sampler::new(WrapMode::Clamp, Filter::Nearest));
+        let at = |u: f32, v: f32| {
+            let c = texture.value(u, v, &Vec3f::zeroed());
+            (c.x(), c.y(), c.z())
+        };
+        assert_eq!(at(0.25, 0.25), (1.0, 0.0, 0.0));
+        assert_eq!(at(0.75, 0.25), (0.0, 1.0, 0.0));
+        assert_eq!(at(0.25, 0.75), (0.0, 0.0, 1.0));
+        assert_eq!(at(0.75, 0.75), (1.0, 1.0, 1.0));
+        let missing = ImageTexture::new(Image::new(0, 0)).value(0.5, 0.5, &Vec3f::zeroed());
+        assert_eq!((missing.x(), missing.y(), missing.z()), (0.0, 1.0, 1.0));
+    }
+}
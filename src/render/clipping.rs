@@ -0,0 +1,110 @@
+use crate::la::{Matrix, Vec3f};
+
+// a vertex of the clipped polygon: its clip space position (x, y, z, w) and
+// its weights of the three vertices of the original triangle
+pub type ClipVertex = ([f32; 4], Vec3f);
+
+// the signed distances to the six planes of the view frustum -w <= x, y <= w and 0 <= z <= w,
+// positive inside
+fn plane_distance(plane: usize, p: &[f32; 4]) -> f32 {
+    match plane {
+        0 => p[3] + p[0],
+        1 => p[3] - p[0],
+        2 => p[3] + p[1],
+        3 => p[3] - p[1],
+        4 => p[2],
+        _ => p[3] - p[2],
+    }
+}
+
+fn lerp(a: &ClipVertex, b: &ClipVertex, t: f32) -> ClipVertex {
+    let p = std::array::from_fn(|i| a.0[i] + (b.0[i] - a.0[i]) * t);
+    (p, a.1 + (b.1 - a.1) * t)
+}
+
+// clip a triangle given in clip space against the view frustum (Sutherland–Hodgman)
+// returns the convex polygon that is left, empty if the triangle is outside
+pub fn clip_triangle(clip: &[Matrix<1, 4>; 3]) -> Vec<ClipVertex> {
+    let mut polygon: Vec<ClipVertex> = vec![
+        ([clip[0][0][0], clip[0][1][0], clip[0][2][0], clip[0][3][0]], Vec3f(1.0, 0.0, 0.0)),
+        ([clip[1][0][0], clip[1][1][0], clip[1][2][0], clip[1][3][0]], Vec3f(0.0, 1.0, 0.0)),
+        ([clip[2][0][0], clip[2][1][0], clip[2][2][0], clip[2][3][0]], Vec3f(0.0, 0.0, 1.0)),
+    ];
+    for plane in 0..6 {
+        if polygon.is_empty() {
+            break;
+        }
+        // nothing to clip, most triangles are inside the frustum
+        if polygon.iter().all(|v| plane_distance(plane, &v.0) >= 0.0) {
+            continue;
+        }
+        let mut output = Vec::with_capacity(polygon.len() + 1);
+        for i in 0..polygon.len() {
+            let current = &polygon[i];
+            let next = &polygon[(i + 1) % polygon.len()];
+            let dc = plane_distance(plane, &current.0);
+            let dn = plane_distance(plane, &next.0);
+            if dc >= 0.0 {
+                output.push(*current);
+            }
+            // the edge crosses the plane
+            if (dc >= 0.0) != (dn >= 0.0) {
+                output.push(lerp(current, next, dc / (dc - dn)));
+            }
+        }
+        polygon = output;
+    }
+    if polygon.len() < 3 {
+        polygon.clear();
+    }
+    polygon
+}
+
+// the perspective divide, from clip space to normalized device coordinates
+pub fn to_ndc(p: &[f32; 4]) -> Matrix<1, 4> {
+    [[p[0] / p[3]], [p[1] / p[3]], [p[2] / p[3]], [1.0]]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clip_inside() {
+        let clip = [
+            [[-0.5], [-0.5], [0.0], [1.0]],
+            [[0.5], [-0.5], [0.0], [1.0]],
+            [[0.0], [0.5], [0.0], [1.0]],
+        ];
+        assert_eq!(clip_triangle(&clip).len(), 3);
+    }
+
+    #[test]
+    fn test_clip_outside() {
+        let clip = [
+            [[2.0], [2.0], [0.0], [1.0]],
+            [[3.0], [2.0], [0.0], [1.0]],
+            [[2.0], [3.0], [0.0], [1.0]],
+        ];
+        assert!(clip_triangle(&clip).is_empty());
+    }
+
+    #[test]
+    fn test_clip_near_plane() {
+        // one vertex behind the camera (negative w)
+        let clip = [
+            [[-0.5], [0.0], [0.5], [1.0]],
+            [[0.5], [0.0], [0.5], [1.0]],
+            [[0.0], [0.0], [-2.0], [-1.0]],
+        ];
+        let polygon = clip_triangle(&clip);
+        assert!(polygon.len() >= 3);
+        for (p, weights) in polygon {
+            assert!(p[3] > 0.0);
+            for plane in 0..6 {
+                assert!(plane_distance(plane, &p) >= -1e-6);
+            }
+            assert!((weights.0 + weights.1 + weights.2 - 1.0).abs() < 1e-6);
+        }
+    }
+}
@@ -12,6 +12,9 @@ pub struct RasterizationConfig {
     pub height: u32,
     pub width: u32,
     pub shader_config: ShaderConf,
+    // samples per pixel for multisample anti-aliasing: 1 (off), 2, 4 or 8,
+    // other counts are rounded down to one of them
+    pub samples: usize,
 }
 
 pub enum RenderConfig {
@@ -2,7 +2,19 @@ mod shader_render;
 mod render_type;
 mod traits;
 mod config;
+mod clipping;
+mod shadow;
+mod ssao;
+mod tiles;
 
-pub use shader_render::{triangle,line};
+pub use shader_render::{line,draw_face};
+pub use shadow::{shadow_maps,shadow_lit,ShadowMap,SHADOW_BIAS};
+pub use ssao::{GBuffer,geometry_buffer,ssao,apply_occlusion};
+pub use tiles::{FrameBuffer,ShaderFactory,draw_tiled};
+// the web page draws on a single thread
+#[cfg(not(feature = "raytracing"))]
+pub use tiles::draw_serial;
+#[cfg(not(feature = "raytracing"))]
+pub use render_type::SceneShaders;
 pub use traits::Render;
 pub use config::{RenderConfig,RasterizationConfig,RayTracingConfig};
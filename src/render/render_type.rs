@@ -13,10 +13,10 @@ use crate::la::{
 };
 use crate::models::MeshObject;
 use crate::raytracing::{ExposureCamera, Exposure};
-use crate::scene::{RayTracingScene, RasterizableScene};
-use crate::shader::{BasicShader, Shader, LightShader, ShaderConf};
-use crate::tga::ZBuffer;
+use crate::scene::{RayTracingScene, RasterizableScene, Light};
+use crate::shader::{BasicShader, LightShader, MeshShader, PbrShader, Shader, ShaderConf};
 use crate::utils::ray_color;
+use crate::transform::get_viewport_matrix;
 use crate::{
     tga::{Image,Color},
 };
@@ -24,7 +24,15 @@ use super::{
     Render, 
     RenderConfig,
     RasterizationConfig,
-    RayTracingConfig, triangle,
+    RayTracingConfig, draw_face,
+    draw_tiled,
+    FrameBuffer,
+    ShaderFactory,
+    shadow_maps,
+    ShadowMap,
+    geometry_buffer,
+    ssao,
+    apply_occlusion,
 };
 use rand::Rng;
 use rayon::iter::IntoParallelIterator;
@@ -96,40 +104,39 @@ impl Render for RenderType {
                 let width = cfg.width as i32;
                 let height = cfg.height as i32;
                 let shader_config = cfg.shader_config;
-                let mut out_texture = Image::new(width, height);
-                let mut z_buffer = ZBuffer::new(width, height);
-                let mut light_texture = Image::new(width, height);
                 let lookat_m = cam.get_lookat().clone();
-                let lookat_mi = lookat_m.inverse().transpose();
-                let light_dir: Vec3f = Vec3f(1.0, -0.0, 0.5).normalize();
-                for obj in rasterscene.objects.iter() {
-                    let mut shader = BasicShader {
-                        conf: shader_config.clone(),
-                        normal_face_vec: None,
-                        light_dir,
-                        lookat_m,
-                        lookat_mi,
-                        model: obj,
-                        out_texture: &mut out_texture,
-                        z_buffer: &mut z_buffer,
-                        varying_uv: Matrix::zeroed(),
-                        varying_xy: Matrix::zeroed(),
-                        vertices: [Vec3f::zeroed(); 3],
-                        light_texture: &mut light_texture,
-                        project_m: cam.get_projection().clone(),
-                    };
-                    for f in 0..obj.num_faces() {
-                        let mut vertices = [Vec3f::zeroed(), Vec3f::zeroed(), Vec3f::zeroed()];
-                        for v in 0..3 {
-                            vertices[v] = shader.vertex(f, v);
-                        }
-                        triangle(&vertices[0], &vertices[1], &vertices[2], &mut shader);
-                    }
-                }
+                let viewport = get_viewport_matrix(width, height);
+                let lights: Vec<Light> = rasterscene.view_lights(&lookat_m);
+                let objects: Vec<&MeshObject> = rasterscene.objects.iter().map(|o| o.as_ref()).collect();
+                let shadow_maps = if shader_config.shadows {
+                    shadow_maps(&objects, &lookat_m, &lights)
+                } else {
+                    lights.iter().map(|_| None).collect()
+                };
+                let project_m = *cam.get_projection();
+                let mut frame = FrameBuffer::new(width, height, cfg.samples, cam.reversed_z());
+                let faces: Vec<usize> = rasterscene.objects.iter().map(|o| o.num_faces()).collect();
+                let shaders = SceneShaders {
+                    conf: shader_config.clone(),
+                    objects: objects.clone(),
+                    lights,
+                    shadow_maps,
+                    lookat_m,
+                    project_m,
+                };
+                draw_tiled(&mut frame, &faces, &viewport, &shaders);
+                frame.resolve();
+                let FrameBuffer { mut out_texture, mut light_texture, mut z_buffer, .. } = frame;
 
                 let light_model = MeshObject::screen_texture_model();
 
-                if shader_config.occlusion {
+                if shader_config.occlusion && shader_config.ssao {
+                    let gbuffer = geometry_buffer(&objects, &lookat_m, &project_m, &viewport, width, height, cam.reversed_z());
+                    let occlusion = ssao(&gbuffer, &project_m, &viewport, shader_config.ssao_radius);
+                    // the pbr shader lights the image itself
+                    let light = if shader_config.pbr { None } else { Some(&light_texture) };
+                    apply_occlusion(&mut out_texture, light, &occlusion);
+                } else if shader_config.occlusion {
                     let mut occl_texture = Image::new(width, height);
                     let mut light_shader = LightShader {
                         conf: ShaderConf::new(),
@@ -143,17 +150,62 @@ impl Render for RenderType {
                     };
 
                     for f in 0..light_model.num_faces() {
-                        let mut vertices = [Vec3f::zeroed(), Vec3f::zeroed(), Vec3f::zeroed()];
-                        for v in 0..3 {
-                            vertices[v] = light_shader.vertex(f, v);
-                        }
-                        triangle(&vertices[0], &vertices[1], &vertices[2], &mut light_shader);
+                        draw_face(f, &viewport, &mut light_shader);
                     }
                 }
 
-                out_texture.apply_gamma(1.5);
+                // the pbr shader already tone-maps and gamma corrects
+                if !shader_config.pbr {
+                    out_texture.apply_gamma(1.5);
+                }
                 Ok(out_texture)
             },
         }
     }
-}
\ No newline at end of file
+}
+// the shaders of the main pass for the objects of a scene
+pub struct SceneShaders<'a> {
+    pub conf: ShaderConf,
+    pub objects: Vec<&'a MeshObject>,
+    // the lights in view space
+    pub lights: Vec<Light>,
+    pub shadow_maps: Vec<Option<ShadowMap>>,
+    pub lookat_m: Matrix<4, 4>,
+    pub project_m: Matrix<4, 4>,
+}
+
+impl ShaderFactory for SceneShaders<'_> {
+    fn shader<'a>(&'a self, object: usize, frame: &'a mut FrameBuffer) -> Box<dyn Shader + 'a> {
+        let FrameBuffer { out_texture, light_texture, z_buffer, color_samples, .. } = frame;
+        let model = self.objects[object];
+        let model_m = model.transform.matrix();
+        let lookat_mi = self.lookat_m.mul(&model_m).inverse().transpose();
+        let mesh = MeshShader {
+            conf: self.conf.clone(),
+            normal_face_vec: None,
+            varying_tbn: [[Vec3f::zeroed(); 3]; 3],
+            face: 0,
+            lights: self.lights.clone(),
+            model_m,
+            lookat_m: self.lookat_m,
+            lookat_mi,
+            model,
+            out_texture,
+            z_buffer,
+            color_samples: color_samples.as_mut(),
+            shadow_maps: &self.shadow_maps,
+            varying_uv: Matrix::zeroed(),
+            varying_xy: Matrix::zeroed(),
+            varying_bar: Matrix::identity(),
+            varying_w: Vec3f(1.0, 1.0, 1.0),
+            vertices: [Vec3f::zeroed(); 3],
+            light_texture,
+            project_m: self.project_m,
+        };
+        if self.conf.pbr {
+            Box::new(PbrShader { mesh })
+        } else {
+            Box::new(BasicShader { mesh })
+        }
+    }
+}
@@ -1,47 +1,230 @@
 use std::mem;
 
 use crate::{
-    la::{Vec3f},
+    la::{Matrix, MatrixI, Vec3f},
     shader::Shader,
     tga::{self},
-    transform::{barycentric},
 };
 
+use super::clipping::{clip_triangle, to_ndc};
 
+// run the vertex shader on a face, clip it against the view frustum in clip space,
+// then rasterize what is left as a triangle fan
+pub fn draw_face(face: usize, viewport: &Matrix<4, 4>, sh: &mut dyn Shader) {
+    let rect = (0, 0, i32::MAX, i32::MAX);
+    for t in screen_triangles(face, viewport, sh) {
+        draw_triangle_in(&t, &rect, sh);
+    }
+}
 
-pub fn triangle(v1: &Vec3f, v2: &Vec3f, v3: &Vec3f, sh: &mut dyn Shader) {
-    let z = Vec3f(v2.0, v2.1, v2.2)
-        .sub(&Vec3f(v1.0, v1.1, v1.2))
-        .cross(&Vec3f(v3.0, v3.1, v3.2).sub(&Vec3f(v1.0, v1.1, v1.2)));
+// a triangle of a clipped face, ready to be rasterized
+#[derive(Clone, Copy)]
+pub struct ScreenTriangle {
+    // the screen space positions of the corners
+    pub screen: [Vec3f; 3],
+    // 1/w of the corners
+    pub inv_w: Vec3f,
+    // column i holds the weights of the face's vertices for corner i
+    pub weights: Matrix<3, 3>,
+}
 
-    // clip space 
-    // clip z<0
-    if z.2 < 0.0 {
-        return;
+impl ScreenTriangle {
+    // the pixels the triangle may cover: (x0, y0, x1, y1), inclusive
+    pub fn bounds(&self) -> (i32, i32, i32, i32) {
+        let [a, b, c] = self.screen;
+        (
+            a.0.min(b.0).min(c.0).floor() as i32,
+            a.1.min(b.1).min(c.1).floor() as i32,
+            a.0.max(b.0).max(c.0).ceil() as i32,
+            a.1.max(b.1).max(c.1).ceil() as i32,
+        )
     }
+}
 
-    let x0 = vec![v1.0, v2.0, v3.0]
-        .iter()
-        .fold(&v1.0, |xmin, x| if xmin > x { x } else { xmin })
-        .round() as i32;
-    let y0 = vec![v1.1, v2.1, v3.1]
-        .iter()
-        .fold(&v1.1, |ymin, y| if ymin > y { y } else { ymin })
-        .round() as i32;
-    let x1 = vec![v1.0, v2.0, v3.0]
+// run the vertex shader on a face and clip it against the view frustum in clip space,
+// what is left as a triangle fan in screen space
+pub fn screen_triangles(face: usize, viewport: &Matrix<4, 4>, sh: &mut dyn Shader) -> Vec<ScreenTriangle> {
+    let clip = [sh.vertex(face, 0), sh.vertex(face, 1), sh.vertex(face, 2)];
+    let polygon = clip_triangle(&clip);
+    if polygon.is_empty() {
+        return Vec::new();
+    }
+    let screen: Vec<Vec3f> = polygon
         .iter()
-        .fold(&v1.0, |xmax, x| if xmax < x { x } else { xmax })
-        .round() as i32;
-    let y1 = vec![v1.1, v2.1, v3.1]
+        .map(|(p, _)| viewport.mul(&to_ndc(p)).into())
+        .collect();
+    (1..polygon.len() - 1)
+        .map(|i| {
+            let corners = [0, i, i + 1];
+            let mut weights: Matrix<3, 3> = Matrix::zeroed();
+            for (j, &c) in corners.iter().enumerate() {
+                let w = polygon[c].1;
+                weights[0][j] = w.0;
+                weights[1][j] = w.1;
+                weights[2][j] = w.2;
+            }
+            ScreenTriangle {
+                screen: corners.map(|c| screen[c]),
+                inv_w: Vec3f(
+                    1.0 / polygon[0].0[3],
+                    1.0 / polygon[i].0[3],
+                    1.0 / polygon[i + 1].0[3],
+                ),
+                weights,
+            }
+        })
+        .collect()
+}
+
+// rasterize a triangle of a face the vertex stage already ran on, visiting only the pixels
+// inside rect: (x0, y0, x1, y1), inclusive.
+// The shader gets screen coordinates relative to the corner x0,y0 of rect
+pub fn draw_triangle_in(t: &ScreenTriangle, rect: &(i32, i32, i32, i32), sh: &mut dyn Shader) {
+    let [a, b, c] = t.screen;
+    let local = t.screen.map(|v| Vec3f(v.0 - rect.0 as f32, v.1 - rect.1 as f32, v.2));
+    sh.set_triangle(&local, &t.inv_w, &t.weights);
+    triangle(&a, &b, &c, rect, sh);
+}
+
+// the bits of sub-pixel precision the corners are snapped to
+const SUB_PIXEL_BITS: i64 = 8;
+const SUB_PIXEL: i64 = 1 << SUB_PIXEL_BITS;
+
+// an edge function of the triangle, in fixed point: positive on the inside, left of the edge a->b
+struct Edge {
+    // the value at the center of the first pixel of the bounding box
+    origin: i64,
+    // the change of the value from one pixel to the next in x and in y
+    step_x: i64,
+    step_y: i64,
+    // -1 if points on the edge are outside (the top-left fill rule), 0 otherwise
+    bias: i64,
+}
+
+impl Edge {
+    fn new(a: (i64, i64), b: (i64, i64), p: (i64, i64)) -> Self {
+        let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+        // with y up and the inside on the left, left edges go down and top edges go left,
+        // so an edge shared by two triangles belongs to exactly one of them
+        let top_left = dy < 0 || (dy == 0 && dx < 0);
+        Edge {
+            origin: dx * (p.1 - a.1) - dy * (p.0 - a.0),
+            step_x: -dy * SUB_PIXEL,
+            step_y: dx * SUB_PIXEL,
+            bias: if top_left { 0 } else { -1 },
+        }
+    }
+
+    // the value at a point offset from a pixel center by o, in fixed point
+    fn offset(&self, value: i64, o: (i64, i64)) -> i64 {
+        value + (self.step_x * o.0 + self.step_y * o.1) / SUB_PIXEL
+    }
+
+    fn inside(&self, value: i64) -> bool {
+        value + self.bias >= 0
+    }
+}
+
+// rasterize a triangle given in screen space with edge functions, calling the shader for
+// every pixel whose center it covers. Pixels on an edge shared by two triangles are drawn once
+// rect: (x0, y0, x1, y1), inclusive, the pixels to visit
+pub fn triangle(v1: &Vec3f, v2: &Vec3f, v3: &Vec3f, rect: &(i32, i32, i32, i32), sh: &mut dyn Shader) {
+    let snap = |v: &Vec3f| {
+        (
+            (v.0 * SUB_PIXEL as f32).round() as i64,
+            (v.1 * SUB_PIXEL as f32).round() as i64,
+        )
+    };
+    let (a, b, c) = (snap(v1), snap(v2), snap(v3));
+
+    // twice the area, negative for triangles facing away
+    let area = (b.0 - a.0) * (c.1 - a.1) - (b.1 - a.1) * (c.0 - a.0);
+    if area <= 0 {
+        return;
+    }
+
+    let offsets = sample_offsets(sh.samples());
+    // samples reach into the pixels around the bounding box of the pixel centers
+    let margin = if offsets.is_empty() { 0 } else { SUB_PIXEL / 2 };
+    let ceil = |v: i64| (v + SUB_PIXEL - 1).div_euclid(SUB_PIXEL);
+    let x0 = ceil(a.0.min(b.0).min(c.0) - margin).max(rect.0 as i64);
+    let y0 = ceil(a.1.min(b.1).min(c.1) - margin).max(rect.1 as i64);
+    let x1 = (a.0.max(b.0).max(c.0) + margin).div_euclid(SUB_PIXEL).min(rect.2 as i64);
+    let y1 = (a.1.max(b.1).max(c.1) + margin).div_euclid(SUB_PIXEL).min(rect.3 as i64);
+    if x0 > x1 || y0 > y1 {
+        return;
+    }
+
+    // the edge opposite of each corner, its value over the area is the barycentric coordinate
+    let start = (x0 * SUB_PIXEL, y0 * SUB_PIXEL);
+    let edges = [Edge::new(b, c, start), Edge::new(c, a, start), Edge::new(a, b, start)];
+    let bar = |e: [i64; 3]| Vec3f(e[0] as f32 / area as f32, e[1] as f32 / area as f32, e[2] as f32 / area as f32);
+    let offsets: Vec<(i64, i64)> = offsets
         .iter()
-        .fold(&v1.1, |ymax, y| if ymax < y { y } else { ymax })
-        .round() as i32;
+        .map(|(dx, dy)| ((dx * SUB_PIXEL as f32) as i64, (dy * SUB_PIXEL as f32) as i64))
+        .collect();
 
-    for y in y0..=y1 {
-        for x in x0..=x1 {
-            let bc = barycentric(v1, v2, v3, (x as f32, y as f32));
-            sh.fragment(&bc);
+    let mut row = [edges[0].origin, edges[1].origin, edges[2].origin];
+    for _y in y0..=y1 {
+        let mut e = row;
+        for _x in x0..=x1 {
+            let inside = (0..3).all(|i| edges[i].inside(e[i]));
+            if offsets.is_empty() {
+                if inside {
+                    sh.fragment(&bar(e));
+                }
+            } else {
+                // the coverage of every sample, the shading is still done once per pixel
+                let covered: Vec<(usize, Vec3f)> = offsets
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(s, &o)| {
+                        let es = [edges[0].offset(e[0], o), edges[1].offset(e[1], o), edges[2].offset(e[2], o)];
+                        (0..3).all(|i| edges[i].inside(es[i])).then(|| (s, bar(es)))
+                    })
+                    .collect();
+                // shade at the center of the pixel if it is inside, otherwise at a covered sample
+                if inside {
+                    sh.fragment_samples(&bar(e), &covered);
+                } else if let Some(&(_, at)) = covered.first() {
+                    sh.fragment_samples(&at, &covered);
+                }
+            }
+            for (v, edge) in e.iter_mut().zip(edges.iter()) {
+                *v += edge.step_x;
+            }
         }
+        for (v, edge) in row.iter_mut().zip(edges.iter()) {
+            *v += edge.step_y;
+        }
+    }
+}
+
+// the positions of the samples in a pixel relative to its center,
+// the standard 2x/4x/8x patterns, empty without multisampling
+pub fn sample_offsets(samples: usize) -> &'static [(f32, f32)] {
+    const MSAA2: [(f32, f32); 2] = [(0.25, 0.25), (-0.25, -0.25)];
+    const MSAA4: [(f32, f32); 4] = [
+        (-0.125, -0.375),
+        (0.375, -0.125),
+        (-0.375, 0.125),
+        (0.125, 0.375),
+    ];
+    const MSAA8: [(f32, f32); 8] = [
+        (0.0625, -0.1875),
+        (-0.0625, 0.1875),
+        (0.3125, 0.0625),
+        (-0.1875, -0.3125),
+        (-0.3125, 0.3125),
+        (-0.4375, -0.0625),
+        (0.1875, 0.4375),
+        (0.4375, -0.4375),
+    ];
+    match samples {
+        2 => &MSAA2,
+        4 => &MSAA4,
+        8 => &MSAA8,
+        _ => &[],
     }
 }
 
@@ -78,3 +261,121 @@ pub fn line(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: i32 = 32;
+
+    // counts how often every pixel (or sample) is drawn
+    struct CoverageShader {
+        corners: [Vec3f; 3],
+        samples: usize,
+        counts: Vec<u32>,
+    }
+
+    impl Shader for CoverageShader {
+        fn vertex(&mut self, _face: usize, _vertex: usize) -> Matrix<1, 4> {
+            Matrix::zeroed()
+        }
+
+        fn set_triangle(&mut self, _screen: &[Vec3f; 3], _inv_w: &Vec3f, _weights: &Matrix<3, 3>) {}
+
+        fn samples(&self) -> usize {
+            self.samples
+        }
+
+        fn fragment(&mut self, bar: &Vec3f) {
+            self.fragment_samples(bar, &[(0, *bar)]);
+        }
+
+        fn fragment_samples(&mut self, bar: &Vec3f, samples: &[(usize, Vec3f)]) {
+            let [a, b, c] = self.corners;
+            let x = (a.0 * bar.0 + b.0 * bar.1 + c.0 * bar.2).round() as i32;
+            let y = (a.1 * bar.0 + b.1 * bar.1 + c.1 * bar.2).round() as i32;
+            for (s, _) in samples {
+                self.counts[(x + y * SIZE) as usize * self.samples.max(1) + s] += 1;
+            }
+        }
+    }
+
+    // a quad split into n x n cells of two triangles each, its corners off the pixel grid
+    // and the edges inside it through pixel centers
+    fn tessellated_quad(n: usize) -> Vec<[Vec3f; 3]> {
+        let grid = |i: usize, j: usize| {
+            let at = |k: usize| {
+                if k == 0 {
+                    2.3
+                } else if k == n {
+                    29.6
+                } else {
+                    2.0 + (k * 27 / n) as f32 + ((i + j) % 2) as f32 * 0.5
+                }
+            };
+            Vec3f(at(i), at(j), 0.0)
+        };
+        let mut triangles = Vec::new();
+        for j in 0..n {
+            for i in 0..n {
+                let (p00, p10, p01, p11) = (grid(i, j), grid(i + 1, j), grid(i, j + 1), grid(i + 1, j + 1));
+                triangles.push([p00, p10, p11]);
+                triangles.push([p00, p11, p01]);
+            }
+        }
+        triangles
+    }
+
+    fn coverage(triangles: &[[Vec3f; 3]], samples: usize) -> CoverageShader {
+        let mut sh = CoverageShader {
+            corners: [Vec3f::zeroed(); 3],
+            samples,
+            counts: vec![0; (SIZE * SIZE) as usize * samples.max(1)],
+        };
+        for t in triangles {
+            sh.corners = *t;
+            triangle(&t[0], &t[1], &t[2], &(0, 0, SIZE - 1, SIZE - 1), &mut sh);
+        }
+        sh
+    }
+
+    #[test]
+    fn test_watertight_quad() {
+        let sh = coverage(&tessellated_quad(6), 1);
+        for y in 0..SIZE {
+            for x in 0..SIZE {
+                let inside = (3..=29).contains(&x) && (3..=29).contains(&y);
+                assert_eq!(sh.counts[(x + y * SIZE) as usize], inside as u32, "pixel {} {}", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn test_watertight_fan_samples() {
+        // triangles around a center on a pixel center, with edges of every slope
+        let center = Vec3f(16.0, 16.0, 0.0);
+        let n = 24;
+        let triangles: Vec<[Vec3f; 3]> = (0..n)
+            .map(|i| {
+                let corner = |k: usize| {
+                    let a = std::f32::consts::TAU * k as f32 / n as f32;
+                    Vec3f(16.0 + 12.0 * a.cos(), 16.0 + 12.0 * a.sin(), 0.0)
+                };
+                [center, corner(i), corner(i + 1)]
+            })
+            .collect();
+        let sh = coverage(&triangles, 4);
+        // no sample is drawn twice and the samples around the center are all drawn
+        assert!(sh.counts.iter().all(|&c| c <= 1));
+        for y in 12..=20 {
+            for x in 12..=20 {
+                for s in 0..4 {
+                    assert_eq!(sh.counts[(x + y * SIZE) as usize * 4 + s], 1);
+                }
+            }
+        }
+        // a triangle facing away is not drawn
+        let back = coverage(&[[center, Vec3f(16.0, 28.0, 0.0), Vec3f(28.0, 16.0, 0.0)]], 1);
+        assert!(back.counts.iter().all(|&c| c == 0));
+    }
+}
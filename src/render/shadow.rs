@@ -0,0 +1,104 @@
+use crate::{
+    la::{Matrix, MatrixI, Vec3f},
+    models::MeshObject,
+    scene::Light,
+    shader::DepthShader,
+    tga::ZBuffer,
+    transform::{
+        calculate_lookat_matrix, calculate_orthographic_projection, get_viewport_matrix,
+        transform_point,
+    },
+};
+
+use super::draw_face;
+
+pub const SHADOW_MAP_SIZE: i32 = 1024;
+// the smallest depth bias against shadow acne, as a fraction of the depth range of a shadow map
+pub const SHADOW_BIAS: f32 = 0.004;
+
+// the depth seen from a light, and the matrix from view space to the map's screen space
+pub type ShadowMap = (ZBuffer, Matrix<4, 4>);
+
+// a shadow map for every light given in view space, only directional lights cast shadows
+pub fn shadow_maps(
+    objects: &[&MeshObject],
+    lookat_m: &Matrix<4, 4>,
+    lights: &[Light],
+) -> Vec<Option<ShadowMap>> {
+    lights
+        .iter()
+        .map(|light| match light {
+            Light::Directional { direction, .. } => Some(shadow_map(objects, lookat_m, direction)),
+            _ => None,
+        })
+        .collect()
+}
+
+// render the depth of the objects seen from a directional light into a shadow map
+// light_dir points to the light in view space
+pub fn shadow_map(
+    objects: &[&MeshObject],
+    lookat_m: &Matrix<4, 4>,
+    light_dir: &Vec3f,
+) -> ShadowMap {
+    // bounding sphere of the objects in view space
+    let mut minimum = Vec3f(f32::MAX, f32::MAX, f32::MAX);
+    let mut maximum = Vec3f(f32::MIN, f32::MIN, f32::MIN);
+    for obj in objects {
+        let model_view = lookat_m.mul(&obj.transform.matrix());
+        for f in 0..obj.num_faces() {
+            for v in 0..3 {
+                let p = transform_point(&model_view, &obj.vertex(f, v));
+                minimum = minimum.min(&p);
+                maximum = maximum.max(&p);
+            }
+        }
+    }
+    let center = minimum.add(&maximum).mulf(0.5);
+    let radius = maximum.sub(&minimum).length() / 2.0 + 1e-3;
+
+    // look at the center from the light, any up vector that is not parallel will do
+    let light_dir = light_dir.normalize();
+    let up = if light_dir.1.abs() > 0.99 {
+        Vec3f(1.0, 0.0, 0.0)
+    } else {
+        Vec3f(0.0, 1.0, 0.0)
+    };
+    let light_lookat = calculate_lookat_matrix(center.add(&light_dir.mulf(radius)), center, up);
+    let projection =
+        calculate_orthographic_projection(-radius, radius, -radius, radius, 0.0, 2.0 * radius);
+    let light_m = projection.mul(&light_lookat);
+
+    let viewport = get_viewport_matrix(SHADOW_MAP_SIZE, SHADOW_MAP_SIZE);
+    let mut z_buffer = ZBuffer::new(SHADOW_MAP_SIZE, SHADOW_MAP_SIZE);
+    for obj in objects {
+        let mut shader = DepthShader {
+            model: obj,
+            light_m: light_m.mul(lookat_m).mul(&obj.transform.matrix()),
+            z_buffer: &mut z_buffer,
+            varying_xy: Matrix::zeroed(),
+        };
+        for f in 0..obj.num_faces() {
+            draw_face(f, &viewport, &mut shader);
+        }
+    }
+    (z_buffer, viewport.mul(&light_m))
+}
+
+// the fraction of the light reaching a view space position, 3x3 percentage closer filtering
+pub fn shadow_lit(shadow_map: &ZBuffer, shadow_m: &Matrix<4, 4>, p: &Vec3f, bias: f32) -> f32 {
+    let s = transform_point(shadow_m, p);
+    let x = s.0.round() as i32;
+    let y = s.1.round() as i32;
+    let clamp = |x: i32, y: i32| (x.clamp(0, shadow_map.width - 1), y.clamp(0, shadow_map.height - 1));
+    let mut lit = 0.0;
+    for dy in -1..=1 {
+        for dx in -1..=1 {
+            let (sx, sy) = clamp(x + dx, y + dy);
+            if s.2 - bias <= shadow_map.pixel_at(sx, sy) {
+                lit += 1.0;
+            }
+        }
+    }
+    lit / 9.0
+}
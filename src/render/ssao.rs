@@ -0,0 +1,252 @@
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    la::{Matrix, MatrixI, Vec3f},
+    models::MeshObject,
+    shader::GeometryShader,
+    tga::{Color, Image, ZBuffer},
+};
+
+use super::draw_face;
+
+const KERNEL_SIZE: usize = 16;
+// the random rotations repeat in tiles of NOISE_SIZE x NOISE_SIZE pixels, the blur averages them out
+const NOISE_SIZE: i32 = 4;
+
+// view space position and normal of the closest surface of every pixel
+pub struct GBuffer {
+    pub width: i32,
+    pub height: i32,
+    pub depth: ZBuffer,
+    pub data: Vec<Option<(Vec3f, Vec3f)>>,
+}
+
+impl GBuffer {
+    pub fn new(width: i32, height: i32, reversed_z: bool) -> Self {
+        GBuffer {
+            width,
+            height,
+            depth: ZBuffer::new(width, height).with_reversed_z(reversed_z),
+            data: vec![None; (width * height) as usize],
+        }
+    }
+
+    pub fn pixel_at(&self, x: i32, y: i32) -> Option<(Vec3f, Vec3f)> {
+        if x < 0 || x >= self.width || y < 0 || y >= self.height {
+            return None;
+        }
+        self.data[(x + y * self.width) as usize]
+    }
+
+    pub fn set_pixel(&mut self, x: i32, y: i32, position: Vec3f, normal: Vec3f) {
+        self.data[(x + y * self.width) as usize] = Some((position, normal));
+    }
+}
+
+// render the view space positions and normals of the objects
+pub fn geometry_buffer(
+    objects: &[&MeshObject],
+    lookat_m: &Matrix<4, 4>,
+    project_m: &Matrix<4, 4>,
+    viewport: &Matrix<4, 4>,
+    width: i32,
+    height: i32,
+    reversed_z: bool,
+) -> GBuffer {
+    let mut gbuffer = GBuffer::new(width, height, reversed_z);
+    for obj in objects {
+        let mut shader = GeometryShader {
+            model: obj,
+            model_view: lookat_m.mul(&obj.transform.matrix()),
+            project_m: *project_m,
+            gbuffer: &mut gbuffer,
+            varying_xy: Matrix::zeroed(),
+            varying_bar: Matrix::identity(),
+            varying_w: Vec3f(1.0, 1.0, 1.0),
+            vertices: [Vec3f::zeroed(); 3],
+        };
+        for f in 0..obj.num_faces() {
+            draw_face(f, viewport, &mut shader);
+        }
+    }
+    gbuffer
+}
+
+// samples in the hemisphere around +z, more of them close to the center
+fn sample_kernel(rng: &mut StdRng) -> Vec<Vec3f> {
+    (0..KERNEL_SIZE)
+        .map(|i| {
+            let sample = Vec3f(
+                rng.gen::<f32>() * 2.0 - 1.0,
+                rng.gen::<f32>() * 2.0 - 1.0,
+                rng.gen::<f32>(),
+            )
+            .normalize()
+            .mulf(rng.gen::<f32>());
+            let scale = i as f32 / KERNEL_SIZE as f32;
+            sample.mulf(0.1 + 0.9 * scale * scale)
+        })
+        .collect()
+}
+
+// the ambient occlusion of every pixel, 1 where nothing occludes and None where there is no surface
+// radius: the size of the sampled hemisphere in view space
+pub fn ssao(
+    gbuffer: &GBuffer,
+    project_m: &Matrix<4, 4>,
+    viewport: &Matrix<4, 4>,
+    radius: f32,
+) -> Vec<Option<f32>> {
+    // a fixed seed, the pattern must not change from frame to frame
+    let mut rng = StdRng::seed_from_u64(7);
+    let kernel = sample_kernel(&mut rng);
+    let noise: Vec<Vec3f> = (0..NOISE_SIZE * NOISE_SIZE)
+        .map(|_| Vec3f(rng.gen::<f32>() * 2.0 - 1.0, rng.gen::<f32>() * 2.0 - 1.0, 0.0))
+        .collect();
+    let screen_m = viewport.mul(project_m);
+
+    let mut occlusion = vec![None; (gbuffer.width * gbuffer.height) as usize];
+    for y in 0..gbuffer.height {
+        for x in 0..gbuffer.width {
+            let (position, normal) = match gbuffer.pixel_at(x, y) {
+                Some(g) => g,
+                None => continue,
+            };
+            // a tangent frame around the normal, rotated by the noise of the pixel
+            let random = noise[((x % NOISE_SIZE) + (y % NOISE_SIZE) * NOISE_SIZE) as usize];
+            let tangent = random.sub(&normal.mulf(random.mul(&normal))).normalize();
+            let bitangent = normal.cross(&tangent);
+
+            let mut occluded = 0.0;
+            for k in kernel.iter() {
+                let sample = position.add(
+                    &tangent
+                        .mulf(k.0)
+                        .add(&bitangent.mulf(k.1))
+                        .add(&normal.mulf(k.2))
+                        .mulf(radius),
+                );
+                let ss: Vec3f = screen_m.mul(&sample.embed::<4>(1f32)).into();
+                let scene = match gbuffer.pixel_at(ss.0.round() as i32, ss.1.round() as i32) {
+                    Some((p, _)) => p,
+                    None => continue,
+                };
+                // the camera looks along -z, a larger z is closer to it
+                if scene.2 >= sample.2 + 0.025 * radius {
+                    // ignore surfaces far away in depth, they do not occlude
+                    let range = (radius / (position.2 - scene.2).abs()).min(1.0);
+                    occluded += range;
+                }
+            }
+            occlusion[(x + y * gbuffer.width) as usize] = Some(1.0 - occluded / KERNEL_SIZE as f32);
+        }
+    }
+    blur(&occlusion, gbuffer.width, gbuffer.height)
+}
+
+// average over the noise tile, to remove the pattern of the random rotations
+fn blur(occlusion: &[Option<f32>], width: i32, height: i32) -> Vec<Option<f32>> {
+    let half = NOISE_SIZE / 2;
+    let mut out = vec![None; occlusion.len()];
+    for y in 0..height {
+        for x in 0..width {
+            if occlusion[(x + y * width) as usize].is_none() {
+                continue;
+            }
+            let mut sum = 0.0;
+            let mut count = 0.0;
+            for yy in (y - half).max(0)..(y + half).min(height) {
+                for xx in (x - half).max(0)..(x + half).min(width) {
+                    if let Some(o) = occlusion[(xx + yy * width) as usize] {
+                        sum += o;
+                        count += 1.0;
+                    }
+                }
+            }
+            out[(x + y * width) as usize] = Some(sum / count);
+        }
+    }
+    out
+}
+
+// darken the image by the occlusion
+// light_texture: the lighting the shader left out of the image, like for the LightShader,
+// None if the image is already lit
+pub fn apply_occlusion(out_texture: &mut Image, light_texture: Option<&Image>, occlusion: &[Option<f32>]) {
+    for y in 0..out_texture.height {
+        for x in 0..out_texture.width {
+            let ao = match occlusion[(x + y * out_texture.width) as usize] {
+                Some(ao) => ao,
+                None => continue,
+            };
+            let c = out_texture.pixel_at(x, y);
+            let c = match light_texture {
+                Some(light_texture) => {
+                    let light = (2.0 * light_texture.pixel_at(x, y).0 as f32 / 255.0) * 2.0 - 2.0;
+                    c.highlight(light - 2.0 * (1.0 - ao))
+                }
+                None => Color(
+                    (c.0 as f32 * ao) as u8,
+                    (c.1 as f32 * ao) as u8,
+                    (c.2 as f32 * ao) as u8,
+                ),
+            };
+            out_texture.set_pixel(x, y, c);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transform::get_viewport_matrix;
+
+    const SIZE: i32 = 32;
+
+    // a gbuffer seen through an orthographic projection, the view space x and y are in [-1, 1]
+    // depth: the z of the surface facing the camera at x
+    fn gbuffer(depth: impl Fn(f32) -> f32) -> GBuffer {
+        let mut gbuffer = GBuffer::new(SIZE, SIZE, false);
+        let half = SIZE as f32 / 2.0;
+        for y in 0..SIZE {
+            for x in 0..SIZE {
+                let (vx, vy) = ((x as f32 - (half - 0.5)) / half, (y as f32 - (half - 0.5)) / half);
+                gbuffer.set_pixel(x, y, Vec3f(vx, vy, depth(vx)), Vec3f(0.0, 0.0, 1.0));
+            }
+        }
+        gbuffer
+    }
+
+    fn occlusion_at(gbuffer: &GBuffer, x: i32, y: i32) -> f32 {
+        let occlusion = ssao(gbuffer, &Matrix::identity(), &get_viewport_matrix(SIZE, SIZE), 0.3);
+        occlusion[(x + y * SIZE) as usize].unwrap()
+    }
+
+    #[test]
+    fn test_flat_plane() {
+        let plane = gbuffer(|_| -2.0);
+        for (x, y) in [(0, 0), (16, 16), (31, 10)] {
+            assert!(occlusion_at(&plane, x, y) > 0.99);
+        }
+    }
+
+    #[test]
+    fn test_inner_corner() {
+        // a step towards the camera at x = 0.25, the floor next to it is in the inner corner
+        let step = gbuffer(|x| if x < 0.25 { -2.0 } else { -1.8 });
+        let corner = (0.25 * SIZE as f32 / 2.0 + SIZE as f32 / 2.0) as i32 - 1;
+        assert!(occlusion_at(&step, corner, 16) < 0.97);
+        // away from the step the floor is open
+        assert!(occlusion_at(&step, 2, 16) > 0.99);
+    }
+
+    #[test]
+    fn test_blur_skips_empty_pixels() {
+        let occlusion = [None, Some(0.2), Some(0.4), None];
+        let blurred = blur(&occlusion, 4, 1);
+        assert_eq!(blurred[0], None);
+        assert_eq!(blurred[3], None);
+        assert!((blurred[1].unwrap() - 0.3).abs() < 1e-6);
+        assert!((blurred[2].unwrap() - 0.3).abs() < 1e-6);
+    }
+}
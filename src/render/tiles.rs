@@ -0,0 +1,308 @@
+use rayon::prelude::*;
+
+use crate::{
+    la::Matrix,
+    shader::{FaceVaryings, Shader},
+    tga::{Image, SampleBuffer, ZBuffer},
+};
+
+use super::shader_render::{draw_triangle_in, screen_triangles, ScreenTriangle};
+
+// the size in pixels of the square tiles the screen is split into
+pub const TILE_SIZE: i32 = 64;
+// the number of faces a thread bins at once
+const BIN_CHUNK: usize = 1024;
+
+// makes the shader of an object drawing into a frame buffer, so that every tile can have its own
+pub trait ShaderFactory {
+    fn shader<'a>(&'a self, object: usize, frame: &'a mut FrameBuffer) -> Box<dyn Shader + 'a>;
+}
+
+// the buffers the main pass draws into
+pub struct FrameBuffer {
+    pub width: i32,
+    pub height: i32,
+    pub out_texture: Image,
+    pub light_texture: Image,
+    pub z_buffer: ZBuffer,
+    // the colors of the samples with multisampling
+    pub color_samples: Option<SampleBuffer>,
+}
+
+impl FrameBuffer {
+    // samples: per pixel, the counts without a multisampling pattern are rounded down to 1, 2, 4 or 8
+    pub fn new(width: i32, height: i32, samples: usize, reversed_z: bool) -> Self {
+        let samples = [8, 4, 2, 1].into_iter().find(|&s| s <= samples).unwrap_or(1);
+        FrameBuffer {
+            width,
+            height,
+            out_texture: Image::new(width, height),
+            light_texture: Image::new(width, height),
+            z_buffer: ZBuffer::with_samples(width, height, samples).with_reversed_z(reversed_z),
+            color_samples: if samples > 1 {
+                Some(SampleBuffer::new(width, height, samples))
+            } else {
+                None
+            },
+        }
+    }
+
+    // average the samples into out_texture
+    pub fn resolve(&mut self) {
+        if let Some(buffer) = &self.color_samples {
+            buffer.resolve(&mut self.out_texture);
+        }
+    }
+
+    // copy a tile drawn on its own into this at x0,y0
+    fn blit(&mut self, tile: &FrameBuffer, x0: i32, y0: i32) {
+        let samples = self.z_buffer.samples;
+        for y in 0..tile.height {
+            let src = (y * tile.width) as usize;
+            let dst = (x0 + (y0 + y) * self.width) as usize;
+            let n = tile.width as usize;
+            self.out_texture.data[dst..dst + n].copy_from_slice(&tile.out_texture.data[src..src + n]);
+            self.light_texture.data[dst..dst + n].copy_from_slice(&tile.light_texture.data[src..src + n]);
+            self.z_buffer.data[dst * samples..(dst + n) * samples]
+                .copy_from_slice(&tile.z_buffer.data[src * samples..(src + n) * samples]);
+            if let (Some(to), Some(from)) = (self.color_samples.as_mut(), tile.color_samples.as_ref()) {
+                to.data[dst * samples..(dst + n) * samples]
+                    .copy_from_slice(&from.data[src * samples..(src + n) * samples]);
+            }
+        }
+    }
+}
+
+// draw every face of every object in order on the current thread
+// faces: the number of faces of each object
+pub fn draw_serial(frame: &mut FrameBuffer, faces: &[usize], viewport: &Matrix<4, 4>, shaders: &dyn ShaderFactory) {
+    for (obj, &n) in faces.iter().enumerate() {
+        let mut shader = shaders.shader(obj, frame);
+        for f in 0..n {
+            super::draw_face(f, viewport, shader.as_mut());
+        }
+    }
+}
+
+// a face after the vertex stage and clipping, what the tiles draw
+struct BinnedFace {
+    varyings: Option<FaceVaryings>,
+    triangles: Vec<ScreenTriangle>,
+}
+
+// run the vertex stage and clip every face once, sort the faces into the tiles they overlap,
+// then rasterize the tiles in parallel, each into its own frame buffer, and put them together.
+// The faces of a tile are drawn in the same order as draw_serial, so the result is the same
+pub fn draw_tiled(frame: &mut FrameBuffer, faces: &[usize], viewport: &Matrix<4, 4>, shaders: &(dyn ShaderFactory + Sync)) {
+    let (samples, reversed_z) = (frame.z_buffer.samples, frame.z_buffer.reversed);
+    let columns = (frame.width + TILE_SIZE - 1) / TILE_SIZE;
+    let rows = (frame.height + TILE_SIZE - 1) / TILE_SIZE;
+
+    // for every object, its faces as they are drawn
+    let binned: Vec<Vec<BinnedFace>> = faces
+        .iter()
+        .enumerate()
+        .map(|(obj, &n)| {
+            (0..n)
+                .into_par_iter()
+                .chunks(BIN_CHUNK)
+                .flat_map_iter(|chunk| {
+                    // the vertex stage doesn't touch the buffers
+                    let mut scratch = FrameBuffer::new(0, 0, 1, false);
+                    let mut shader = shaders.shader(obj, &mut scratch);
+                    chunk
+                        .into_iter()
+                        .map(|f| {
+                            let triangles = screen_triangles(f, viewport, shader.as_mut());
+                            BinnedFace { varyings: shader.face_varyings(), triangles }
+                        })
+                        .collect::<Vec<_>>()
+                })
+                .collect()
+        })
+        .collect();
+
+    // for every tile, the faces of every object that overlap it
+    let mut bins: Vec<Vec<Vec<usize>>> = vec![vec![Vec::new(); faces.len()]; (columns * rows) as usize];
+    for (obj, object_faces) in binned.iter().enumerate() {
+        for (f, face) in object_faces.iter().enumerate() {
+            let (x0, y0, x1, y1) = match face_bounds(&face.triangles) {
+                Some(b) => b,
+                None => continue,
+            };
+            let (c0, c1) = (x0.div_euclid(TILE_SIZE).max(0), x1.div_euclid(TILE_SIZE).min(columns - 1));
+            let (r0, r1) = (y0.div_euclid(TILE_SIZE).max(0), y1.div_euclid(TILE_SIZE).min(rows - 1));
+            for r in r0..=r1 {
+                for c in c0..=c1 {
+                    bins[(c + r * columns) as usize][obj].push(f);
+                }
+            }
+        }
+    }
+
+    let tiles: Vec<(i32, i32, FrameBuffer)> = bins
+        .into_par_iter()
+        .enumerate()
+        .map(|(i, bin)| {
+            let x0 = (i as i32 % columns) * TILE_SIZE;
+            let y0 = (i as i32 / columns) * TILE_SIZE;
+            let width = TILE_SIZE.min(frame.width - x0);
+            let height = TILE_SIZE.min(frame.height - y0);
+            let mut tile = FrameBuffer::new(width, height, samples, reversed_z);
+            // the shaders draw relative to the corner of the tile, into its own buffers
+            let rect = (x0, y0, x0 + width - 1, y0 + height - 1);
+            for (obj, faces) in bin.iter().enumerate().filter(|(_, faces)| !faces.is_empty()) {
+                let mut shader = shaders.shader(obj, &mut tile);
+                for &f in faces {
+                    let face = &binned[obj][f];
+                    if let Some(varyings) = &face.varyings {
+                        shader.set_face_varyings(varyings);
+                    }
+                    for t in face.triangles.iter() {
+                        draw_triangle_in(t, &rect, shader.as_mut());
+                    }
+                }
+            }
+            (x0, y0, tile)
+        })
+        .collect();
+
+    for (x0, y0, tile) in tiles.iter() {
+        frame.blit(tile, *x0, *y0);
+    }
+}
+
+// the screen space bounding box of the triangles of a face, None if it was clipped away
+fn face_bounds(triangles: &[ScreenTriangle]) -> Option<(i32, i32, i32, i32)> {
+    triangles.iter().map(|t| t.bounds()).reduce(|a, b| (a.0.min(b.0), a.1.min(b.1), a.2.max(b.2), a.3.max(b.3)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::{CameraTrait, PerspectiveCamera, Projectable};
+    use crate::la::Vec3f;
+    use crate::models::{MeshObject, Wavefront};
+    use crate::render::render_type::SceneShaders;
+    use crate::render::shader_render::sample_offsets;
+    use crate::scene::Light;
+    use crate::shader::ShaderConf;
+    use crate::transform::{get_viewport_matrix, Transform};
+    use test::Bencher;
+
+    const WIDTH: i32 = 600;
+    const HEIGHT: i32 = 400;
+
+    fn heads() -> Vec<MeshObject> {
+        let head = || MeshObject::from_file("res/african_head/model.obj".into()).unwrap();
+        vec![
+            head(),
+            head().with_transform(Transform::new(
+                Vec3f(1.5, 0.0, -1.0),
+                Vec3f(0.0, -0.8, 0.0),
+                Vec3f(0.6, 0.6, 0.6),
+            )),
+        ]
+    }
+
+    fn shaders(objects: &[MeshObject]) -> SceneShaders<'_> {
+        shaders_with(objects, false)
+    }
+
+    fn shaders_with(objects: &[MeshObject], reversed_z: bool) -> SceneShaders<'_> {
+        let mut cam = PerspectiveCamera::new(50.0, 1.5, 0.01, 1000.0).with_reversed_z(reversed_z);
+        cam.set_position(Vec3f(0.5, 0.5, 3.5));
+        let lookat_m = *cam.get_lookat();
+        SceneShaders {
+            conf: ShaderConf::new(),
+            objects: objects.iter().collect(),
+            lights: vec![Light::default()],
+            shadow_maps: vec![None],
+            lookat_m,
+            project_m: *cam.get_projection(),
+        }
+    }
+
+    #[test]
+    fn test_sample_counts() {
+        for (samples, expected) in [(0, 1), (1, 1), (2, 2), (3, 2), (4, 4), (7, 4), (8, 8), (16, 8)] {
+            let frame = FrameBuffer::new(4, 4, samples, false);
+            assert_eq!(frame.z_buffer.samples, expected, "{}", samples);
+            assert_eq!(frame.color_samples.as_ref().map_or(1, |b| b.samples), expected);
+            // every sample has a position, so none is left black
+            if expected > 1 {
+                assert_eq!(sample_offsets(expected).len(), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_reversed_z() {
+        let quad = |z: f32| {
+            let wf = Wavefront::parse_string("v -1 -1 0\nv 1 -1 0\nv 1 1 0\nv -1 1 0\nf 1 2 3 4\n").unwrap();
+            MeshObject::new(wf, Image::new(0, 0), Image::new(0, 0))
+                .with_transform(Transform::new(Vec3f(0.0, 0.0, z), Vec3f::zeroed(), Vec3f(1.0, 1.0, 1.0)))
+        };
+        let viewport = get_viewport_matrix(WIDTH, HEIGHT);
+        let depth = |objects: Vec<MeshObject>| {
+            let shaders = shaders_with(&objects, true);
+            let faces: Vec<usize> = objects.iter().map(|o| o.num_faces()).collect();
+            let mut serial = FrameBuffer::new(WIDTH, HEIGHT, 1, true);
+            draw_serial(&mut serial, &faces, &viewport, &shaders);
+            let mut tiled = FrameBuffer::new(WIDTH, HEIGHT, 1, true);
+            draw_tiled(&mut tiled, &faces, &viewport, &shaders);
+            assert_eq!(serial.z_buffer.data, tiled.z_buffer.data);
+            serial.z_buffer.pixel_at(WIDTH / 2, HEIGHT / 2)
+        };
+        let (near, far) = (depth(vec![quad(0.5)]), depth(vec![quad(-0.5)]));
+        // the nearer quad is closer to 1, and covers the other one drawn in either order
+        assert!(near > far && far > 0.0, "{} {}", near, far);
+        assert_eq!(depth(vec![quad(-0.5), quad(0.5)]), near);
+        assert_eq!(depth(vec![quad(0.5), quad(-0.5)]), near);
+    }
+
+    #[test]
+    fn test_tiled_matches_serial() {
+        let objects = heads();
+        let shaders = shaders(&objects);
+        let faces: Vec<usize> = objects.iter().map(|o| o.num_faces()).collect();
+        let viewport = get_viewport_matrix(WIDTH, HEIGHT);
+        let mut serial = FrameBuffer::new(WIDTH, HEIGHT, 1, false);
+        draw_serial(&mut serial, &faces, &viewport, &shaders);
+        let mut tiled = FrameBuffer::new(WIDTH, HEIGHT, 1, false);
+        draw_tiled(&mut tiled, &faces, &viewport, &shaders);
+        assert_eq!(serial.z_buffer.data, tiled.z_buffer.data);
+        assert!(serial
+            .out_texture
+            .data
+            .iter()
+            .zip(tiled.out_texture.data.iter())
+            .all(|(a, b)| (a.0, a.1, a.2) == (b.0, b.1, b.2)));
+    }
+
+    #[bench]
+    fn bench_serial(b: &mut Bencher) {
+        let objects = heads();
+        let shaders = shaders(&objects);
+        let faces: Vec<usize> = objects.iter().map(|o| o.num_faces()).collect();
+        let viewport = get_viewport_matrix(WIDTH, HEIGHT);
+        b.iter(|| {
+            let mut frame = FrameBuffer::new(WIDTH, HEIGHT, 1, false);
+            draw_serial(&mut frame, &faces, &viewport, &shaders);
+            frame
+        });
+    }
+
+    #[bench]
+    fn bench_tiled(b: &mut Bencher) {
+        let objects = heads();
+        let shaders = shaders(&objects);
+        let faces: Vec<usize> = objects.iter().map(|o| o.num_faces()).collect();
+        let viewport = get_viewport_matrix(WIDTH, HEIGHT);
+        b.iter(|| {
+            let mut frame = FrameBuffer::new(WIDTH, HEIGHT, 1, false);
+            draw_tiled(&mut frame, &faces, &viewport, &shaders);
+            frame
+        });
+    }
+}
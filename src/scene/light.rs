@@ -0,0 +1,175 @@
+use crate::{
+    la::{Matrix, Vec3f},
+    transform::{transform_point, transform_vector},
+};
+
+// a light of a rasterizable scene, positions and directions in world space
+// color is in [0,1] per channel
+#[derive(Debug, Clone)]
+pub enum Light {
+    // infinitely far away, `direction` points towards the light
+    Directional {
+        direction: Vec3f,
+        color: Vec3f,
+        intensity: f32,
+    },
+    // attenuation: the constant, linear and quadratic factors of the distance
+    Point {
+        position: Vec3f,
+        color: Vec3f,
+        intensity: f32,
+        attenuation: Vec3f,
+    },
+    // a point light restricted to a cone around `direction`, which points away from the light
+    // inner, outer: the cosines of the cone angles, the light fades out in between
+    Spot {
+        position: Vec3f,
+        direction: Vec3f,
+        color: Vec3f,
+        intensity: f32,
+        attenuation: Vec3f,
+        inner: f32,
+        outer: f32,
+    },
+}
+
+const DEFAULT_ATTENUATION: Vec3f = Vec3f(1.0, 0.09, 0.032);
+
+// the default light comes from the right, in view space: it is not moved by the camera
+impl Default for Light {
+    fn default() -> Self {
+        Light::directional(Vec3f(1.0, -0.0, 0.5), Vec3f(1.0, 1.0, 1.0), 1.0)
+    }
+}
+
+impl Light {
+    pub fn directional(direction: Vec3f, color: Vec3f, intensity: f32) -> Self {
+        Light::Directional {
+            direction: direction.normalize(),
+            color,
+            intensity,
+        }
+    }
+
+    pub fn point(position: Vec3f, color: Vec3f, intensity: f32) -> Self {
+        Light::Point {
+            position,
+            color,
+            intensity,
+            attenuation: DEFAULT_ATTENUATION,
+        }
+    }
+
+    // angle: the half angle of the cone, the outer 20% of it fade out
+    pub fn spot(position: Vec3f, direction: Vec3f, angle_in_radians: f32, color: Vec3f, intensity: f32) -> Self {
+        Light::Spot {
+            position,
+            direction: direction.normalize(),
+            color,
+            intensity,
+            attenuation: DEFAULT_ATTENUATION,
+            inner: (angle_in_radians * 0.8).cos(),
+            outer: angle_in_radians.cos(),
+        }
+    }
+
+    // replace the attenuation of point and spot lights
+    pub fn with_attenuation(mut self, factors: Vec3f) -> Self {
+        match &mut self {
+            Light::Point { attenuation, .. } | Light::Spot { attenuation, .. } => *attenuation = factors,
+            Light::Directional { .. } => {}
+        }
+        self
+    }
+
+    // the light moved by an affine matrix, e.g. into view space
+    pub fn transformed(&self, m: &Matrix<4, 4>) -> Self {
+        let mut light = self.clone();
+        match &mut light {
+            Light::Directional { direction, .. } => {
+                *direction = transform_vector(m, direction).normalize();
+            }
+            Light::Point { position, .. } => {
+                *position = transform_point(m, position);
+            }
+            Light::Spot { position, direction, .. } => {
+                *position = transform_point(m, position);
+                *direction = transform_vector(m, direction).normalize();
+            }
+        }
+        light
+    }
+
+    // the direction from p towards the light, and the light's color scaled by
+    // its intensity and falloff at p
+    pub fn illuminate(&self, p: &Vec3f) -> (Vec3f, Vec3f) {
+        match self {
+            Light::Directional { direction, color, intensity } => (*direction, color.mulf(*intensity)),
+            Light::Point { position, color, intensity, attenuation } => {
+                let to_light = position.sub(p);
+                let falloff = attenuate(attenuation, to_light.length());
+                (to_light.normalize(), color.mulf(intensity * falloff))
+            }
+            Light::Spot { position, direction, color, intensity, attenuation, inner, outer } => {
+                let to_light = position.sub(p);
+                let l = to_light.normalize();
+                let cos = -l.mul(direction);
+                let cone = ((cos - outer) / (inner - outer).max(1e-4)).clamp(0.0, 1.0);
+                let falloff = attenuate(attenuation, to_light.length()) * cone;
+                (l, color.mulf(intensity * falloff))
+            }
+        }
+    }
+}
+
+fn attenuate(attenuation: &Vec3f, distance: f32) -> f32 {
+    1.0 / (attenuation.0 + attenuation.1 * distance + attenuation.2 * distance * distance)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WHITE: Vec3f = Vec3f(1.0, 1.0, 1.0);
+
+    #[test]
+    fn test_point_falloff() {
+        let light = Light::point(Vec3f(0.0, 2.0, 0.0), WHITE, 1.0);
+        let (dir, near) = light.illuminate(&Vec3f(0.0, 1.0, 0.0));
+        assert!((dir.1 - 1.0).abs() < 1e-6);
+        assert!((near.0 - attenuate(&DEFAULT_ATTENUATION, 1.0)).abs() < 1e-6);
+        let mut last = near.0;
+        for d in [2.0, 4.0, 8.0, 16.0] {
+            let (_, strength) = light.illuminate(&Vec3f(0.0, 2.0 - d, 0.0));
+            assert!(strength.0 < last, "{} at {}", strength.0, d);
+            last = strength.0;
+        }
+        // no falloff without attenuation
+        let constant = light.with_attenuation(Vec3f(1.0, 0.0, 0.0));
+        assert!((constant.illuminate(&Vec3f(0.0, -10.0, 0.0)).1 .0 - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_spot_cone() {
+        let angle = 0.5f32;
+        let light = Light::spot(Vec3f::zeroed(), Vec3f(0.0, 0.0, -1.0), angle, WHITE, 1.0)
+            .with_attenuation(Vec3f(1.0, 0.0, 0.0));
+        // the strength at angle a from the axis of the spot
+        let at = |a: f32| light.illuminate(&Vec3f(a.sin(), 0.0, -a.cos())).1 .0;
+        assert!((at(0.0) - 1.0).abs() < 1e-6);
+        assert!((at(angle * 0.8 - 0.01) - 1.0).abs() < 1e-6);
+        assert_eq!(at(angle + 0.01), 0.0);
+        assert_eq!(at(2.0), 0.0);
+        // behind the light
+        assert_eq!(light.illuminate(&Vec3f(0.0, 0.0, 1.0)).1 .0, 0.0);
+        // between the cones the strength falls smoothly from 1 to 0
+        let steps = 100;
+        let mut last = at(angle * 0.8);
+        for i in 1..=steps {
+            let s = at(angle * (0.8 + 0.2 * i as f32 / steps as f32));
+            assert!(s <= last && last - s < 0.05, "{} after {}", s, last);
+            last = s;
+        }
+        assert!(last.abs() < 1e-4);
+    }
+}
@@ -1,9 +1,11 @@
 mod traits;
 mod raytracing_scene;
 mod rasterizable_scene;
+mod light;
 
 
 pub use traits::SceneTrait;
 
 pub use raytracing_scene::RayTracingScene;
-pub use rasterizable_scene::RasterizableScene;
\ No newline at end of file
+pub use rasterizable_scene::RasterizableScene;
+pub use light::Light;
\ No newline at end of file
@@ -1,25 +1,43 @@
-use super::SceneTrait;
+use super::{Light, SceneTrait};
 use crate::{
+    la::Matrix,
     models::MeshObject,
 };
 pub struct RasterizableScene {
     // objects: ,
     pub objects: Vec<Box<MeshObject>>,
+    pub lights: Vec<Light>,
+}
+
+impl RasterizableScene {
+    pub fn add_light(&mut self, light: Light) {
+        self.lights.push(light);
+    }
+
+    // the lights of the scene moved into view space by lookat_m
+    // without lights, the default directional light, which stays fixed relative to the camera
+    pub fn view_lights(&self, lookat_m: &Matrix<4, 4>) -> Vec<Light> {
+        if self.lights.is_empty() {
+            vec![Light::default()]
+        } else {
+            self.lights.iter().map(|light| light.transformed(lookat_m)).collect()
+        }
+    }
 }
 
 impl SceneTrait for RasterizableScene {
     type ObjectType = Box<MeshObject>;
 
     fn new() -> Self {
-        Self { objects: vec![] }
+        Self { objects: vec![], lights: vec![] }
     }
 
     fn clear(&mut self) {
         self.objects.clear();
+        self.lights.clear();
     }
 
     fn add(&mut self, object: <Self as SceneTrait>::ObjectType) {
         self.objects.push(object);
     }
 }
-
@@ -1,19 +1,72 @@
 use super::SceneTrait;
-use crate::raytracing::{Hittable,Hit,Ray};
+use crate::la::Vec3f;
+use crate::raytracing::{Hittable,Hit,Ray,BoundingBox,BvhNode,Environment};
+use rand::Rng;
+use std::sync::Arc;
 
 pub struct RayTracingScene {
     pub objects: Vec<Box<dyn Hittable>>,
+    // the bounded objects, once `build_bvh` has been called
+    pub bvh: Option<BvhNode>,
+    // the light of the rays that hit nothing
+    pub environment: Environment,
+    // the emitters sampled explicitly by the path tracer, they are in `objects` (or the bvh) as well
+    pub lights: Vec<Arc<dyn Hittable>>,
+}
+
+impl RayTracingScene {
+    // add an emissive object to the scene and register it for light sampling
+    pub fn add_light(&mut self, light: Arc<dyn Hittable>) {
+        self.objects.push(Box::new(light.clone()));
+        self.lights.push(light);
+    }
+
+    // the probability density of `sample_light` picking `direction`, the lights are picked uniformly
+    pub fn light_pdf(&self, origin: &Vec3f, direction: &Vec3f) -> f32 {
+        if self.lights.is_empty() {
+            return 0.0;
+        }
+        let sum: f32 = self.lights.iter().map(|light| light.pdf_value(origin, direction)).sum();
+        sum / self.lights.len() as f32
+    }
+
+    // a random direction from `origin` towards one of the lights, None without lights
+    pub fn sample_light(&self, origin: &Vec3f) -> Option<Vec3f> {
+        if self.lights.is_empty() {
+            return None;
+        }
+        let i = rand::thread_rng().gen_range(0, self.lights.len());
+        Some(self.lights[i].random_direction(origin))
+    }
+
+    // move every bounded object into a bvh, the unbounded ones stay in the linear list
+    // objects added afterwards are kept in the linear list until the next call
+    pub fn build_bvh(&mut self) {
+        let (mut bounded, unbounded): (Vec<_>, Vec<_>) = self
+            .objects
+            .drain(..)
+            .partition(|object| object.bounding_box().is_some());
+        self.objects = unbounded;
+        if let Some(bvh) = self.bvh.take() {
+            bounded.push(Box::new(bvh));
+        }
+        if !bounded.is_empty() {
+            self.bvh = Some(BvhNode::new(bounded));
+        }
+    }
 }
 
 impl SceneTrait for RayTracingScene {
     type ObjectType = Box<dyn Hittable>;
 
     fn new() -> Self {
-        Self { objects: vec![] }
+        Self { objects: vec![], bvh: None, environment: Default::default(), lights: vec![] }
     }
 
     fn clear(&mut self) {
         self.objects.clear();
+        self.bvh = None;
+        self.lights.clear();
     }
 
     fn add(&mut self, object: <Self as SceneTrait>::ObjectType) {
@@ -27,6 +80,10 @@ impl Hittable for RayTracingScene {
     fn hit(&self, t_min: f32, t_max: f32, r: &Ray) -> Option<Hit> {
         let mut temp_rec: Option<Hit> = None;
         let mut closest_so_far = t_max;
+        if let Some(rec) = self.bvh.as_ref().and_then(|bvh| bvh.hit(t_min, closest_so_far, r)) {
+            closest_so_far = rec.t;
+            temp_rec = Some(rec);
+        }
         for object in &self.objects {
             let result = object.hit(t_min, closest_so_far, r);
             if let Some(rec) = result {
@@ -37,7 +94,17 @@ impl Hittable for RayTracingScene {
         }
         temp_rec
     }
+
+    fn bounding_box(&self) -> Option<BoundingBox> {
+        let mut bbox = self.bvh.as_ref().and_then(|bvh| bvh.bounding_box());
+        for object in &self.objects {
+            let b = object.bounding_box()?;
+            bbox = Some(match bbox {
+                Some(bbox) => bbox.surrounding(&b),
+                None => b,
+            });
+        }
+        bbox
+    }
     
 }
-
-
@@ -1,71 +1,43 @@
+use super::interpolation::{face_weights, uv_footprint};
+use super::tangent_space::tangent_to_view;
 use super::{
-    ShaderConf,
+    FaceVaryings,
+    MeshShader,
     Shader,
-
 };
 
 use crate::{
     la::{Matrix,Vec3f,MatrixI},
-    models::MeshObject,
-    tga::{self,Color},
-    transform::get_viewport_matrix,
+    models::NormalSpace,
+    transform::transform_vector,
+    render::{shadow_lit, SHADOW_BIAS},
+    tga::Color,
 };
 
 
 pub struct BasicShader<'a> {
-    pub conf: ShaderConf,
-    pub light_dir: Vec3f,
-    pub lookat_m: Matrix<4, 4>,
-    pub lookat_mi: Matrix<4, 4>,
-    pub model: &'a MeshObject,
-    pub out_texture: &'a mut tga::Image,
-    pub light_texture: &'a mut tga::Image,
-    pub z_buffer: &'a mut tga::ZBuffer,
-    pub project_m: Matrix<4, 4>,
-    pub varying_uv: Matrix<3, 2>,
-    pub varying_xy: Matrix<3, 3>,
-    pub vertices: [Vec3f; 3],
-    pub normal_face_vec: Option<Vec3f>,
+    pub mesh: MeshShader<'a>,
 }
 
 impl Shader for BasicShader<'_> {
-    fn vertex(&mut self, face: usize, vertex: usize) -> Vec3f {
-        let v = self.model.vertex(face, vertex);
-        let t = self.model.texture_coords(face, vertex);
-
-        for i in 0..2 {
-            self.varying_uv[i][vertex] = t[i];
-        }
-
-        let perspective_matrix = self.project_m;
-
-        let viewport_matrix = get_viewport_matrix(self.out_texture.width, self.out_texture.height);
-
-        let model_view = self.lookat_m;
-        
-        let ss = viewport_matrix.mul(&perspective_matrix)
-                                        .mul(&model_view)
-                                        .mul(&v.embed::<4>(1f32))
-                                        .into();
+    fn vertex(&mut self, face: usize, vertex: usize) -> Matrix<1, 4> {
+        self.mesh.vertex(face, vertex)
+    }
 
-        self.vertices[vertex] = ss;
+    fn set_triangle(&mut self, screen: &[Vec3f; 3], inv_w: &Vec3f, weights: &Matrix<3, 3>) {
+        self.mesh.set_triangle(screen, inv_w, weights)
+    }
 
-        self.varying_xy[0][vertex] = ss.0;
-        self.varying_xy[1][vertex] = ss.1;
-        self.varying_xy[2][vertex] = ss.2;
+    fn samples(&self) -> usize {
+        self.mesh.z_buffer.samples
+    }
 
-        // todo refactor
-        // set vector that is perpendicular to current triangle
-        if vertex == 2 {
-            self.normal_face_vec = Some(
-                self.vertices[1]
-                    .sub(&self.vertices[0])
-                    .cross(&self.vertices[2].sub(&self.vertices[1]))
-                    .normalize(),
-            );
-        }
+    fn face_varyings(&self) -> Option<FaceVaryings> {
+        Some(self.mesh.face_varyings())
+    }
 
-        ss
+    fn set_face_varyings(&mut self, varyings: &FaceVaryings) {
+        self.mesh.set_face_varyings(varyings)
     }
 
     fn fragment(&mut self, bar: &Vec3f) {
@@ -73,61 +45,126 @@ impl Shader for BasicShader<'_> {
         if bar.0 < 0.0 || bar.1 < 0.0 || bar.2 < 0.0 {
             return;
         }
+        self.fragment_samples(bar, &[(0, *bar)]);
+    }
+
+    fn fragment_samples(&mut self, bar: &Vec3f, samples: &[(usize, Vec3f)]) {
+        let mesh = &mut self.mesh;
         let bar_mtrx = bar.into();
-        let [[x], [y], [z]] = self.varying_xy.mul(&bar_mtrx);
+        let [[x], [y], [_]] = mesh.varying_xy.mul(&bar_mtrx);
         let x = x.round() as i32;
         let y = y.round() as i32;
 
-        // todo!("")
-        // 非线形插值的坐标
-        if z >= self.z_buffer.pixel_at(x, y)
-            || x < 0
-            || x >= self.out_texture.width
+        if x < 0
+            || x >= mesh.out_texture.width
             || y < 0
-            || y >= self.out_texture.height
-            || z < 0.0
+            || y >= mesh.out_texture.height
         {
             return;
         }
+        // depth test each covered sample
+        let zs = mesh.varying_xy[2];
+        let passed: Vec<(usize, f32)> = samples
+            .iter()
+            .map(|(s, b)| (*s, zs[0] * b.0 + zs[1] * b.1 + zs[2] * b.2))
+            .filter(|(s, z)| mesh.z_buffer.depth_test(x, y, *s, *z))
+            .collect();
+        if passed.is_empty() {
+            return;
+        }
 
-        let [[u], [v]] = self.varying_uv.mul(&bar_mtrx);
+        // the weights of the face's vertices
+        let weights = face_weights(bar, &mesh.varying_w, &mesh.varying_bar, mesh.conf.perspective);
+        let Vec3f(b0, b1, b2) = weights;
+        let [[u], [v]] = mesh.varying_uv.mul(&[[b0], [b1], [b2]]);
+        let footprint = uv_footprint(
+            x as f32,
+            y as f32,
+            &mesh.varying_xy,
+            &mesh.varying_uv,
+            &mesh.varying_w,
+            &mesh.varying_bar,
+            mesh.conf.perspective,
+        );
 
-        let txt = if self.conf.texture {
-            self.model.texture(u, v)
-        } else {
-            Color(150, 150, 150)
+        // the mtl material of the face replaces the textures of the model
+        let surface = mesh.model.surface(mesh.face);
+        let txt = match (mesh.conf.texture, surface) {
+            (true, Some(s)) => s.color(&mesh.model.sampler, u, v, footprint),
+            (true, None) => mesh.model.texture(u, v, footprint),
+            (false, _) => Color(150, 150, 150),
         };
-        let normal_vec = if self.conf.normals {
-            self.lookat_mi
-                .mul(&(self.model.normal(u, v)).embed::<4>(0.0))
-                .into()
-        } else {
-            *self.normal_face_vec.as_ref().unwrap()
+        let face_normal = *mesh.normal_face_vec.as_ref().unwrap();
+        let normal_vec = match (mesh.conf.normals, surface) {
+            (true, Some(s)) => s
+                .normal(&mesh.model.sampler, u, v, footprint)
+                .map(|n| tangent_to_view(&mesh.varying_tbn, &weights, &n))
+                .unwrap_or(face_normal),
+            (true, None) => {
+                let n = mesh.model.normal(u, v, footprint);
+                match mesh.model.normal_space {
+                    NormalSpace::Object => transform_vector(&mesh.lookat_mi, &n),
+                    NormalSpace::Tangent => tangent_to_view(&mesh.varying_tbn, &weights, &n),
+                }
+            }
+            (false, _) => face_normal,
         };
         let normal_vec = normal_vec.normalize();
+        // the exponent and strength of the highlight
+        let (shininess, specular) = match surface {
+            Some(s) => (s.shininess, s.material.specular.value(u, v).0),
+            None => (23.0, 0.9),
+        };
 
-        let light = normal_vec.mul(&self.light_dir);
-        let reflected = normal_vec
-            .mulf(normal_vec.mul(&self.light_dir) * 2.0)
-            .sub(&self.light_dir)
-            .normalize();
-        let light_spec = reflected.2.powf(23.0); // cam on z
-
-        let mut highlight = if self.conf.diff_light { light } else { 0.0f32 };
-        highlight += if self.conf.spec_light { light_spec * 0.9 } else { 0.0 };
-
-        let hc = (((highlight + 2.0) / 2.0) * 255.0 / 2.0).round() as u8;
-        self.light_texture.set_pixel(x, y, Color(hc, hc, hc));
-
-        self.out_texture.set_pixel(
-            x,
-            y,
-            if self.conf.occlusion {
-                txt
-            } else {
-                txt.highlight(highlight)
-            },
-        );
-        self.z_buffer.set_pixel(x, y, z)
+        // view space position of the fragment
+        let p = mesh.vertices[0]
+            .mulf(b0)
+            .add(&mesh.vertices[1].mulf(b1))
+            .add(&mesh.vertices[2].mulf(b2));
+
+        let mut highlight = Vec3f::zeroed();
+        for (light, shadow) in mesh.lights.iter().zip(mesh.shadow_maps.iter()) {
+            let (light_dir, strength) = light.illuminate(&p);
+            let mut light = normal_vec.mul(&light_dir);
+            let reflected = normal_vec
+                .mulf(normal_vec.mul(&light_dir) * 2.0)
+                .sub(&light_dir)
+                .normalize();
+            let mut light_spec = reflected.2.powf(shininess); // cam on z
+
+            if let (true, Some((shadow_map, shadow_m))) = (mesh.conf.shadows, shadow) {
+                // more bias where the light grazes the surface
+                let bias = SHADOW_BIAS * (1.0 + 2.0 * (1.0 - light.clamp(0.0, 1.0)));
+                let lit = shadow_lit(shadow_map, shadow_m, &p, bias);
+                // in the shadow the surface is as dark as if it was facing away
+                light = light * lit - light.abs() * (1.0 - lit);
+                light_spec *= lit;
+            }
+
+            let mut h = if mesh.conf.diff_light { light } else { 0.0f32 };
+            h += if mesh.conf.spec_light { light_spec * specular } else { 0.0 };
+            highlight = highlight.add(&strength.mulf(h));
+        }
+
+        let gray = (highlight.0 + highlight.1 + highlight.2) / 3.0;
+        let hc = (((gray.clamp(-2.0, 2.0) + 2.0) / 2.0) * 255.0 / 2.0).round() as u8;
+        mesh.light_texture.set_pixel(x, y, Color(hc, hc, hc));
+
+        let out = if mesh.conf.occlusion {
+            txt
+        } else {
+            txt.highlight_rgb((highlight.0, highlight.1, highlight.2))
+        };
+        let out = match surface {
+            Some(s) => s.emit(out),
+            None => out,
+        };
+        match mesh.color_samples.as_mut() {
+            Some(buffer) => passed.iter().for_each(|(s, _)| buffer.set_sample(x, y, *s, out)),
+            None => mesh.out_texture.set_pixel(x, y, out),
+        }
+        for (s, z) in passed {
+            mesh.z_buffer.set_sample(x, y, s, z);
+        }
     }
 }
@@ -0,0 +1,49 @@
+use crate::{
+    tga,
+    models::MeshObject,
+    la::{Vec3f,Matrix,MatrixI},
+};
+
+use super::Shader;
+
+// only writes the depth, used to render the shadow map from the light's point of view
+pub struct DepthShader<'a> {
+    pub model: &'a MeshObject,
+    // object space to the light's clip space
+    pub light_m: Matrix<4, 4>,
+    pub z_buffer: &'a mut tga::ZBuffer,
+    pub varying_xy: Matrix<3, 3>,
+}
+
+impl Shader for DepthShader<'_> {
+    fn vertex(&mut self, face: usize, vertex: usize) -> Matrix<1, 4> {
+        let v = self.model.vertex(face, vertex);
+        self.light_m.mul(&v.embed::<4>(1f32))
+    }
+
+    fn set_triangle(&mut self, screen: &[Vec3f; 3], _inv_w: &Vec3f, _weights: &Matrix<3, 3>) {
+        for (i, ss) in screen.iter().enumerate() {
+            self.varying_xy[0][i] = ss.0;
+            self.varying_xy[1][i] = ss.1;
+            self.varying_xy[2][i] = ss.2;
+        }
+    }
+
+    fn fragment(&mut self, bar: &Vec3f) {
+        if bar.0 < 0.0 || bar.1 < 0.0 || bar.2 < 0.0 {
+            return;
+        }
+        let [[x], [y], [z]] = self.varying_xy.mul(&bar.into());
+        let x = x.round() as i32;
+        let y = y.round() as i32;
+        if x < 0
+            || x >= self.z_buffer.width
+            || y < 0
+            || y >= self.z_buffer.height
+            || !self.z_buffer.depth_test(x, y, 0, z)
+        {
+            return;
+        }
+        self.z_buffer.set_pixel(x, y, z);
+    }
+}
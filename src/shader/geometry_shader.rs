@@ -0,0 +1,68 @@
+use crate::{
+    la::{Matrix,MatrixI,Vec3f},
+    models::MeshObject,
+    render::GBuffer,
+};
+
+use super::interpolation::face_weights;
+use super::Shader;
+
+// writes the view space position and normal of the closest surface of every pixel
+pub struct GeometryShader<'a> {
+    pub model: &'a MeshObject,
+    // object space to view space
+    pub model_view: Matrix<4, 4>,
+    pub project_m: Matrix<4, 4>,
+    pub gbuffer: &'a mut GBuffer,
+    pub varying_xy: Matrix<3, 3>,
+    pub varying_bar: Matrix<3, 3>,
+    pub varying_w: Vec3f,
+    // view space positions of the face's vertices
+    pub vertices: [Vec3f; 3],
+}
+
+impl Shader for GeometryShader<'_> {
+    fn vertex(&mut self, face: usize, vertex: usize) -> Matrix<1, 4> {
+        let view_pos = self.model_view.mul(&self.model.vertex(face, vertex).embed::<4>(1f32));
+        self.vertices[vertex] = view_pos.into();
+        self.project_m.mul(&view_pos)
+    }
+
+    fn set_triangle(&mut self, screen: &[Vec3f; 3], inv_w: &Vec3f, weights: &Matrix<3, 3>) {
+        for (i, ss) in screen.iter().enumerate() {
+            self.varying_xy[0][i] = ss.0;
+            self.varying_xy[1][i] = ss.1;
+            self.varying_xy[2][i] = ss.2;
+        }
+        self.varying_bar = *weights;
+        self.varying_w = *inv_w;
+    }
+
+    fn fragment(&mut self, bar: &Vec3f) {
+        if bar.0 < 0.0 || bar.1 < 0.0 || bar.2 < 0.0 {
+            return;
+        }
+        let [[x], [y], [z]] = self.varying_xy.mul(&bar.into());
+        let x = x.round() as i32;
+        let y = y.round() as i32;
+        if x < 0
+            || x >= self.gbuffer.width
+            || y < 0
+            || y >= self.gbuffer.height
+            || !self.gbuffer.depth.depth_test(x, y, 0, z)
+        {
+            return;
+        }
+        let w = face_weights(bar, &self.varying_w, &self.varying_bar, true);
+        let position = self.vertices[0]
+            .mulf(w.0)
+            .add(&self.vertices[1].mulf(w.1))
+            .add(&self.vertices[2].mulf(w.2));
+        let normal = self.vertices[1]
+            .sub(&self.vertices[0])
+            .cross(&self.vertices[2].sub(&self.vertices[1]))
+            .normalize();
+        self.gbuffer.depth.set_pixel(x, y, z);
+        self.gbuffer.set_pixel(x, y, position, normal);
+    }
+}
@@ -0,0 +1,43 @@
+use crate::{
+    la::{Matrix, MatrixI, Vec3f},
+    transform::barycentric,
+};
+
+// the weights of the face's vertices at barycentric coordinates `bar` of the clipped triangle
+// z/w is linear in screen space, the varyings are linear in 1/w
+pub fn face_weights(bar: &Vec3f, inv_w: &Vec3f, varying_bar: &Matrix<3, 3>, perspective: bool) -> Vec3f {
+    let bar = if perspective {
+        let b = Vec3f(bar.0 * inv_w.0, bar.1 * inv_w.1, bar.2 * inv_w.2);
+        b.mulf(1.0 / (b.0 + b.1 + b.2))
+    } else {
+        *bar
+    };
+    varying_bar.mul(&(&bar).into()).into()
+}
+
+// how far the u,v move from the pixel (x, y) to its right and upper neighbours, the larger of both
+// used to pick the level of detail of the textures
+pub fn uv_footprint(
+    x: f32,
+    y: f32,
+    varying_xy: &Matrix<3, 3>,
+    varying_uv: &Matrix<3, 2>,
+    inv_w: &Vec3f,
+    varying_bar: &Matrix<3, 3>,
+    perspective: bool,
+) -> f32 {
+    let corner = |i: usize| Vec3f(varying_xy[0][i], varying_xy[1][i], varying_xy[2][i]);
+    let (a, b, c) = (corner(0), corner(1), corner(2));
+    let uv_at = |x: f32, y: f32| {
+        let w = face_weights(&barycentric(&a, &b, &c, (x, y)), inv_w, varying_bar, perspective);
+        let [[u], [v]] = varying_uv.mul(&(&w).into());
+        (u, v)
+    };
+    let (u, v) = uv_at(x, y);
+    let (ux, vx) = uv_at(x + 1.0, y);
+    let (uy, vy) = uv_at(x, y + 1.0);
+    let dx = ((ux - u) * (ux - u) + (vx - v) * (vx - v)).sqrt();
+    let dy = ((uy - u) * (uy - u) + (vy - v) * (vy - v)).sqrt();
+    let footprint = dx.max(dy);
+    if footprint.is_finite() { footprint } else { 0.0 }
+}
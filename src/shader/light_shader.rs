@@ -2,7 +2,6 @@ use crate::{
     tga::{self,Color},
     models::MeshObject,
     la::{Vec3f,Matrix,MatrixI},
-    transform::get_viewport_matrix,
 };
 
 use super::{
@@ -23,16 +22,18 @@ pub struct LightShader<'a> {
 
 impl Shader for LightShader<'_> {
 
-    fn vertex(&mut self, face: usize, vertex: usize) -> Vec3f {
-        let v = self.model.vertex(face, vertex);
-        // calculate the postion in out_texture
-        let viewport_matrix = get_viewport_matrix(self.out_texture.width, self.out_texture.height);
-        let ss: Vec3f = viewport_matrix.mul(&v.embed::<4>(1f32)).into();
-        // the vertex(whose id = vertex)'s  x,y,z (z is calculated in [0,255]) assigned in varying_uv
-        self.varying_xy[0][vertex] = ss.0;
-        self.varying_xy[1][vertex] = ss.1;
-        self.varying_xy[2][vertex] = ss.2;
-        ss
+    fn vertex(&mut self, face: usize, vertex: usize) -> Matrix<1, 4> {
+        // the screen texture model already lies in normalized device coordinates
+        self.model.vertex(face, vertex).embed::<4>(1f32)
+    }
+
+    fn set_triangle(&mut self, screen: &[Vec3f; 3], _inv_w: &Vec3f, _weights: &Matrix<3, 3>) {
+        // the corners' x,y,z (z is calculated in [0,255]) assigned in varying_xy
+        for (i, ss) in screen.iter().enumerate() {
+            self.varying_xy[0][i] = ss.0;
+            self.varying_xy[1][i] = ss.1;
+            self.varying_xy[2][i] = ss.2;
+        }
     }
     
     fn fragment(&mut self, bar: &Vec3f) {
@@ -51,13 +52,13 @@ impl Shader for LightShader<'_> {
         {
             return;
         }
-        let current_z = self.z_buffer.pixel_at(x, y) / 255.0;
+        let current_z = self.z_buffer.depth_at(x, y);
         // let [[u],[v]] = self.varying_uv.mul(&bar_mtrx);
         let mut total = 0.0;
         // hacky screen space ambient occlusion
         for yy in (y - 5).max(0)..(y + 5).min(self.out_texture.height) {
             for xx in (x - 5).max(0)..(x + 5).min(self.out_texture.width) {
-                let surr_z = self.z_buffer.pixel_at(xx, yy) / 255.0;
+                let surr_z = self.z_buffer.depth_at(xx, yy);
                 if current_z <= 0.01 {
                     continue;
                 }
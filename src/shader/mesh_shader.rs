@@ -0,0 +1,106 @@
+use super::tangent_space::view_tangent_frame;
+use super::{FaceVaryings, ShaderConf};
+
+use crate::{
+    la::{Matrix,Vec3f,MatrixI},
+    models::MeshObject,
+    render::ShadowMap,
+    scene::Light,
+    tga,
+};
+
+// the state shared by the shaders that draw a mesh object: the vertex stage, its varyings
+// and the render targets. The shaders embed it and only add their own fragment stage
+pub struct MeshShader<'a> {
+    pub conf: ShaderConf,
+    // the lights in view space
+    pub lights: Vec<Light>,
+    // object space to world space
+    pub model_m: Matrix<4, 4>,
+    pub lookat_m: Matrix<4, 4>,
+    // inverse transpose of lookat_m * model_m, for the normals
+    pub lookat_mi: Matrix<4, 4>,
+    pub model: &'a MeshObject,
+    pub out_texture: &'a mut tga::Image,
+    pub light_texture: &'a mut tga::Image,
+    pub z_buffer: &'a mut tga::ZBuffer,
+    // the colors of the samples with multisampling, resolved into out_texture afterwards
+    pub color_samples: Option<&'a mut tga::SampleBuffer>,
+    // for every light, its shadow map if it casts shadows
+    pub shadow_maps: &'a [Option<ShadowMap>],
+    pub project_m: Matrix<4, 4>,
+    pub varying_uv: Matrix<3, 2>,
+    pub varying_xy: Matrix<3, 3>,
+    // weights of the face's vertices for the corners of the clipped triangle
+    pub varying_bar: Matrix<3, 3>,
+    // 1/w of the corners of the clipped triangle
+    pub varying_w: Vec3f,
+    // view space positions of the face's vertices
+    pub vertices: [Vec3f; 3],
+    pub normal_face_vec: Option<Vec3f>,
+    // view space tangent, bitangent and normal of the face's vertices, for tangent space normal maps
+    pub varying_tbn: [[Vec3f; 3]; 3],
+    // the face being drawn
+    pub face: usize,
+}
+
+impl MeshShader<'_> {
+    pub fn vertex(&mut self, face: usize, vertex: usize) -> Matrix<1, 4> {
+        let v = self.model.vertex(face, vertex);
+        let t = self.model.texture_coords(face, vertex);
+
+        self.varying_uv[0][vertex] = t[0];
+        self.varying_uv[1][vertex] = t[1];
+
+        let model_view = self.lookat_m.mul(&self.model_m);
+        let view_pos = model_view.mul(&v.embed::<4>(1f32));
+
+        self.vertices[vertex] = view_pos.into();
+
+        self.face = face;
+        if self.model.has_tangent_frames() {
+            self.varying_tbn[vertex] =
+                view_tangent_frame(self.model, face, vertex, &model_view, &self.lookat_mi);
+        }
+
+        // todo refactor
+        // set vector that is perpendicular to current triangle
+        if vertex == 2 {
+            self.normal_face_vec = Some(
+                self.vertices[1]
+                    .sub(&self.vertices[0])
+                    .cross(&self.vertices[2].sub(&self.vertices[1]))
+                    .normalize(),
+            );
+        }
+
+        self.project_m.mul(&view_pos)
+    }
+
+    pub fn set_triangle(&mut self, screen: &[Vec3f; 3], inv_w: &Vec3f, weights: &Matrix<3, 3>) {
+        for (i, ss) in screen.iter().enumerate() {
+            self.varying_xy[0][i] = ss.0;
+            self.varying_xy[1][i] = ss.1;
+            self.varying_xy[2][i] = ss.2;
+        }
+        self.varying_bar = *weights;
+        self.varying_w = *inv_w;
+    }
+    pub fn face_varyings(&self) -> FaceVaryings {
+        FaceVaryings {
+            face: self.face,
+            uv: self.varying_uv,
+            vertices: self.vertices,
+            normal: self.normal_face_vec,
+            tbn: self.varying_tbn,
+        }
+    }
+
+    pub fn set_face_varyings(&mut self, varyings: &FaceVaryings) {
+        self.face = varyings.face;
+        self.varying_uv = varyings.uv;
+        self.vertices = varyings.vertices;
+        self.normal_face_vec = varyings.normal;
+        self.varying_tbn = varyings.tbn;
+    }
+}
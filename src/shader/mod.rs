@@ -1,13 +1,27 @@
 mod traits;
 mod shader_config;
+mod mesh_shader;
 mod basic_shader;
 mod light_shader;
+mod depth_shader;
+mod pbr_shader;
+mod tangent_space;
+mod interpolation;
+mod geometry_shader;
 
 
-pub use traits::Shader;
+pub use traits::{FaceVaryings, Shader};
 
 pub use shader_config::ShaderConf;
 
 pub use light_shader::LightShader;
 
+pub use mesh_shader::MeshShader;
+
 pub use basic_shader::BasicShader;
+
+pub use depth_shader::DepthShader;
+
+pub use pbr_shader::PbrShader;
+
+pub use geometry_shader::GeometryShader;
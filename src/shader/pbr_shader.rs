@@ -0,0 +1,229 @@
+use std::f32::consts::PI;
+
+use super::interpolation::{face_weights, uv_footprint};
+use super::tangent_space::tangent_to_view;
+use super::{
+    FaceVaryings,
+    MeshShader,
+    Shader,
+};
+
+use crate::{
+    la::{Matrix,Vec3f,MatrixI},
+    models::NormalSpace,
+    transform::transform_vector,
+    render::{shadow_lit, SHADOW_BIAS},
+    tga::Color,
+};
+
+// ambient light, as a fraction of the albedo
+const AMBIENT: f32 = 0.03;
+
+// physically based shading: a Cook–Torrance BRDF with the GGX distribution, driven by the
+// metallic/roughness material of the mesh. Lighting is computed in linear color,
+// then tone-mapped (Reinhard) and gamma corrected
+pub struct PbrShader<'a> {
+    pub mesh: MeshShader<'a>,
+}
+
+// GGX / Trowbridge-Reitz normal distribution
+fn distribution_ggx(n_dot_h: f32, roughness: f32) -> f32 {
+    let a = roughness * roughness;
+    let a2 = a * a;
+    let d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    a2 / (PI * d * d)
+}
+
+// Smith's geometry term with the Schlick-GGX approximation
+fn geometry_smith(n_dot_v: f32, n_dot_l: f32, roughness: f32) -> f32 {
+    let k = (roughness + 1.0) * (roughness + 1.0) / 8.0;
+    let g1 = |x: f32| x / (x * (1.0 - k) + k);
+    g1(n_dot_v) * g1(n_dot_l)
+}
+
+fn fresnel_schlick(cos_theta: f32, f0: &Vec3f) -> Vec3f {
+    let f = (1.0 - cos_theta).clamp(0.0, 1.0).powi(5);
+    Vec3f(
+        f0.0 + (1.0 - f0.0) * f,
+        f0.1 + (1.0 - f0.1) * f,
+        f0.2 + (1.0 - f0.2) * f,
+    )
+}
+
+fn tone_map(c: f32) -> u8 {
+    ((c / (1.0 + c)).powf(1.0 / 2.2) * 255.0).round().clamp(0.0, 255.0) as u8
+}
+
+impl Shader for PbrShader<'_> {
+    fn vertex(&mut self, face: usize, vertex: usize) -> Matrix<1, 4> {
+        self.mesh.vertex(face, vertex)
+    }
+
+    fn set_triangle(&mut self, screen: &[Vec3f; 3], inv_w: &Vec3f, weights: &Matrix<3, 3>) {
+        self.mesh.set_triangle(screen, inv_w, weights)
+    }
+
+    fn samples(&self) -> usize {
+        self.mesh.z_buffer.samples
+    }
+
+    fn face_varyings(&self) -> Option<FaceVaryings> {
+        Some(self.mesh.face_varyings())
+    }
+
+    fn set_face_varyings(&mut self, varyings: &FaceVaryings) {
+        self.mesh.set_face_varyings(varyings)
+    }
+
+    fn fragment(&mut self, bar: &Vec3f) {
+        // check inside a triangle
+        if bar.0 < 0.0 || bar.1 < 0.0 || bar.2 < 0.0 {
+            return;
+        }
+        self.fragment_samples(bar, &[(0, *bar)]);
+    }
+
+    fn fragment_samples(&mut self, bar: &Vec3f, samples: &[(usize, Vec3f)]) {
+        let mesh = &mut self.mesh;
+        let bar_mtrx = bar.into();
+        let [[x], [y], [_]] = mesh.varying_xy.mul(&bar_mtrx);
+        let x = x.round() as i32;
+        let y = y.round() as i32;
+
+        if x < 0
+            || x >= mesh.out_texture.width
+            || y < 0
+            || y >= mesh.out_texture.height
+        {
+            return;
+        }
+        // depth test each covered sample
+        let zs = mesh.varying_xy[2];
+        let passed: Vec<(usize, f32)> = samples
+            .iter()
+            .map(|(s, b)| (*s, zs[0] * b.0 + zs[1] * b.1 + zs[2] * b.2))
+            .filter(|(s, z)| mesh.z_buffer.depth_test(x, y, *s, *z))
+            .collect();
+        if passed.is_empty() {
+            return;
+        }
+
+        // the weights of the face's vertices
+        let weights = face_weights(bar, &mesh.varying_w, &mesh.varying_bar, mesh.conf.perspective);
+        let Vec3f(b0, b1, b2) = weights;
+        let [[u], [v]] = mesh.varying_uv.mul(&[[b0], [b1], [b2]]);
+        let footprint = uv_footprint(
+            x as f32,
+            y as f32,
+            &mesh.varying_xy,
+            &mesh.varying_uv,
+            &mesh.varying_w,
+            &mesh.varying_bar,
+            mesh.conf.perspective,
+        );
+
+        // view space position of the fragment, the camera is at the origin
+        let p = mesh.vertices[0]
+            .mulf(b0)
+            .add(&mesh.vertices[1].mulf(b1))
+            .add(&mesh.vertices[2].mulf(b2));
+        let view_dir = p.mulf(-1.0).normalize();
+
+        // the mtl material of the face replaces the textures and material of the model
+        let surface = mesh.model.surface(mesh.face);
+        let face_normal = *mesh.normal_face_vec.as_ref().unwrap();
+        let normal_vec = match (mesh.conf.normals, surface) {
+            (true, Some(s)) => s
+                .normal(&mesh.model.sampler, u, v, footprint)
+                .map(|n| tangent_to_view(&mesh.varying_tbn, &weights, &n))
+                .unwrap_or(face_normal),
+            (true, None) => {
+                let n = mesh.model.normal(u, v, footprint);
+                match mesh.model.normal_space {
+                    NormalSpace::Object => transform_vector(&mesh.lookat_mi, &n),
+                    NormalSpace::Tangent => tangent_to_view(&mesh.varying_tbn, &weights, &n),
+                }
+            }
+            (false, _) => face_normal,
+        };
+        let normal_vec = normal_vec.normalize();
+
+        let material = surface.map_or(&mesh.model.material, |s| &s.material);
+        let texture = match surface {
+            Some(s) => s.texture(&mesh.model.sampler, u, v, footprint),
+            None if mesh.model.texture.width > 0 => Some(mesh.model.texture(u, v, footprint)),
+            None => None,
+        };
+        let albedo = if let (true, Some(c)) = (mesh.conf.texture, texture) {
+            Vec3f(
+                (c.0 as f32 / 255.0).powf(2.2),
+                (c.1 as f32 / 255.0).powf(2.2),
+                (c.2 as f32 / 255.0).powf(2.2),
+            )
+        } else {
+            material.albedo(u, v)
+        };
+        let metallic = material.metallic(u, v);
+        let roughness = material.roughness(u, v).max(0.04);
+
+        // reflectance at normal incidence, usually 4% for dielectrics
+        let dielectric = material.f0(u, v);
+        let f0 = Vec3f(dielectric, dielectric, dielectric)
+            .mulf(1.0 - metallic)
+            .add(&albedo.mulf(metallic));
+        let n_dot_v = normal_vec.mul(&view_dir).max(1e-4);
+
+        let mut color = albedo.mulf(AMBIENT).add(&surface.map_or(Vec3f::zeroed(), |s| s.emission));
+        for (light, shadow) in mesh.lights.iter().zip(mesh.shadow_maps.iter()) {
+            let (light_dir, radiance) = light.illuminate(&p);
+            let n_dot_l = normal_vec.mul(&light_dir);
+            if n_dot_l <= 0.0 {
+                continue;
+            }
+            let lit = match (mesh.conf.shadows, shadow) {
+                (true, Some((shadow_map, shadow_m))) => {
+                    let bias = SHADOW_BIAS * (1.0 + 2.0 * (1.0 - n_dot_l));
+                    shadow_lit(shadow_map, shadow_m, &p, bias)
+                }
+                _ => 1.0,
+            };
+            if lit <= 0.0 {
+                continue;
+            }
+            let half = light_dir.add(&view_dir).normalize();
+            let n_dot_h = normal_vec.mul(&half).max(0.0);
+            let fresnel = fresnel_schlick(half.mul(&view_dir).max(0.0), &f0);
+
+            let mut brdf = Vec3f::zeroed();
+            if mesh.conf.diff_light {
+                // the light that is not reflected is refracted, metals absorb it
+                let kd = Vec3f(1.0 - fresnel.0, 1.0 - fresnel.1, 1.0 - fresnel.2).mulf(1.0 - metallic);
+                brdf = brdf.add(&Vec3f(kd.0 * albedo.0, kd.1 * albedo.1, kd.2 * albedo.2).mulf(1.0 / PI));
+            }
+            if mesh.conf.spec_light {
+                let spec = distribution_ggx(n_dot_h, roughness) * geometry_smith(n_dot_v, n_dot_l, roughness)
+                    / (4.0 * n_dot_v * n_dot_l + 1e-4);
+                brdf = brdf.add(&fresnel.mulf(spec));
+            }
+            let scale = n_dot_l * lit;
+            color = color.add(&Vec3f(
+                brdf.0 * radiance.0 * scale,
+                brdf.1 * radiance.1 * scale,
+                brdf.2 * radiance.2 * scale,
+            ));
+        }
+
+        let out = Color(tone_map(color.0), tone_map(color.1), tone_map(color.2));
+        let gray = (color.0 + color.1 + color.2) / 3.0;
+        let hc = tone_map(gray);
+        mesh.light_texture.set_pixel(x, y, Color(hc, hc, hc));
+
+        match mesh.color_samples.as_mut() {
+            Some(buffer) => passed.iter().for_each(|(s, _)| buffer.set_sample(x, y, *s, out)),
+            None => mesh.out_texture.set_pixel(x, y, out),
+        }
+        for (s, z) in passed {
+            mesh.z_buffer.set_sample(x, y, s, z);
+        }
+    }
+}
@@ -6,6 +6,17 @@ pub struct ShaderConf {
     pub texture: bool,
     pub normals: bool,
     pub occlusion: bool,
+    // perspective-correct interpolation of the varyings, affine in screen space otherwise
+    pub perspective: bool,
+    // shadows of the light, from a shadow map rendered in a first pass
+    pub shadows: bool,
+    // physically based shading with the mesh's metallic/roughness material
+    pub pbr: bool,
+    // screen space ambient occlusion from view space depth and normals, instead of the
+    // LightShader's approximation when `occlusion` is on
+    pub ssao: bool,
+    // the radius of the sampled hemisphere, in view space
+    pub ssao_radius: f32,
 }
 
 impl ShaderConf {
@@ -16,6 +27,11 @@ impl ShaderConf {
             texture: true,
             normals: true,
             occlusion: false,
+            perspective: true,
+            shadows: false,
+            pbr: false,
+            ssao: false,
+            ssao_radius: 0.3,
         }
     }
 }
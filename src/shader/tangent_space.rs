@@ -0,0 +1,37 @@
+use crate::{
+    la::{Matrix, Vec3f},
+    models::MeshObject,
+    transform::transform_vector,
+};
+
+// the tangent frame of a vertex moved into view space
+// model_view moves the tangents, normal_m (its inverse transpose) the normal
+pub fn view_tangent_frame(
+    model: &MeshObject,
+    face: usize,
+    vertex: usize,
+    model_view: &Matrix<4, 4>,
+    normal_m: &Matrix<4, 4>,
+) -> [Vec3f; 3] {
+    let [t, b, n] = model.tangent_frame(face, vertex);
+    [
+        transform_vector(model_view, &t),
+        transform_vector(model_view, &b),
+        transform_vector(normal_m, &n),
+    ]
+}
+
+// a normal read from a tangent space map, in view space
+// frames: the view space frames of the face's vertices, bar: the fragment's weights of them
+pub fn tangent_to_view(frames: &[[Vec3f; 3]; 3], bar: &Vec3f, n: &Vec3f) -> Vec3f {
+    let interpolate = |i: usize| {
+        frames[0][i]
+            .mulf(bar.0)
+            .add(&frames[1][i].mulf(bar.1))
+            .add(&frames[2][i].mulf(bar.2))
+    };
+    interpolate(0)
+        .mulf(n.0)
+        .add(&interpolate(1).mulf(n.1))
+        .add(&interpolate(2).mulf(n.2))
+}
@@ -1,7 +1,39 @@
-use crate::la::Vec3f;
+use crate::la::{Matrix, Vec3f};
+
+// what the vertex stage of a mesh shader keeps for a face, so that it can be drawn later
+// without running the vertex stage again
+#[derive(Clone, Copy)]
+pub struct FaceVaryings {
+    pub face: usize,
+    pub uv: Matrix<3, 2>,
+    // view space positions of the vertices
+    pub vertices: [Vec3f; 3],
+    pub normal: Option<Vec3f>,
+    pub tbn: [[Vec3f; 3]; 3],
+}
 
 pub trait Shader {
-    fn vertex(&mut self, face: usize, vertex: usize) -> Vec3f;
+    // the clip space position of the vertex, before the perspective divide
+    fn vertex(&mut self, face: usize, vertex: usize) -> Matrix<1, 4>;
+    // the triangle about to be rasterized, after clipping
+    // screen: the screen space positions of its corners
+    // inv_w: 1/w of its corners, for perspective-correct interpolation
+    // weights: column i holds the weights of the face's vertices for corner i
+    fn set_triangle(&mut self, screen: &[Vec3f; 3], inv_w: &Vec3f, weights: &Matrix<3, 3>);
     fn fragment(&mut self, bar: &Vec3f);
+    // the samples per pixel of the depth buffer the shader draws into
+    fn samples(&self) -> usize {
+        1
+    }
+    // shade a pixel once for the samples of it the triangle covers
+    // bar: where to shade, samples: the covered samples and their barycentric coordinates
+    fn fragment_samples(&mut self, bar: &Vec3f, _samples: &[(usize, Vec3f)]) {
+        self.fragment(bar);
+    }
+    // the varyings of the last face the vertex stage ran on, None if the shader keeps none
+    fn face_varyings(&self) -> Option<FaceVaryings> {
+        None
+    }
+    // restore the varyings of a face saved with face_varyings
+    fn set_face_varyings(&mut self, _varyings: &FaceVaryings) {}
 }
-
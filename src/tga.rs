@@ -1,6 +1,6 @@
+use std::fmt;
 use std::fs::File;
 use std::io;
-use std::io::BufReader;
 use std::io::Read;
 use std::io::Write;
 use std::mem;
@@ -18,59 +18,299 @@ impl Color {
         let fb = ((b as f32) / 255.0).powf(1.0 - p / 2.3);
         Color((fr * 255.0) as u8, (fg * 255.0) as u8, (fb * 255.0) as u8)
     }
+
+    // highlight with a different amount per channel, for colored light
+    pub fn highlight_rgb(self, p: (f32, f32, f32)) -> Self {
+        let Color(r, g, b) = self;
+        Color(
+            Color(r, r, r).highlight(p.0).0,
+            Color(g, g, g).highlight(p.1).0,
+            Color(b, b, b).highlight(p.2).0,
+        )
+    }
+}
+
+// the first bytes of every png file
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+// why an image file could not be read
+#[derive(Clone, Debug, PartialEq)]
+pub enum ImageError {
+    // the data ends before the header or the pixels
+    Truncated,
+    // the color mapped images and the unknown types
+    UnsupportedType(u8),
+    // a pixel depth the image type can not have
+    UnsupportedDepth(u8),
+    // the error of the png decoder
+    Png(String),
+    Io(String),
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ImageError::Truncated => write!(f, "the tga data is truncated"),
+            ImageError::UnsupportedType(t) => write!(f, "unsupported tga image type {}", t),
+            ImageError::UnsupportedDepth(d) => write!(f, "unsupported tga pixel depth {}", d),
+            ImageError::Png(e) => write!(f, "invalid png: {}", e),
+            ImageError::Io(e) => write!(f, "{}", e),
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
 pub struct ColorA(pub u8, pub u8, pub u8, pub u8);
 
 // the struct to save the z_depth value to test depth
+// the depth is 0 at the near plane and 1 at the far plane, or the other way around
+// with reversed-z, which keeps more of the float precision far from the camera
+// with multisampling every pixel keeps the depth of each of its samples
 pub struct ZBuffer {
     pub width: i32,
     pub height: i32,
-    pub data: Vec<f32>, // values between 0 and 255,float
+    pub samples: usize,
+    pub reversed: bool,
+    pub data: Vec<f32>,
 }
 
 impl ZBuffer {
     pub fn new(width: i32, height: i32) -> Self {
-        let v = vec![255f32; (width * height) as usize];
+        ZBuffer::with_samples(width, height, 1)
+    }
+
+    pub fn with_samples(width: i32, height: i32, samples: usize) -> Self {
+        let v = vec![1f32; (width * height) as usize * samples];
         ZBuffer {
             width: width,
             height: height,
+            samples,
+            reversed: false,
             data: v,
         }
     }
 
+    pub fn with_reversed_z(mut self, reversed: bool) -> Self {
+        self.reversed = reversed;
+        let far = self.far();
+        self.data.iter_mut().for_each(|v| *v = far);
+        self
+    }
+
+    // the depth of the far plane, where nothing was drawn
+    pub fn far(&self) -> f32 {
+        if self.reversed { 0.0 } else { 1.0 }
+    }
+
+    // whether depth a is in front of depth b
+    pub fn closer(&self, a: f32, b: f32) -> bool {
+        if self.reversed { a > b } else { a < b }
+    }
+
+    // whether a fragment at depth z is inside the depth range and in front of the sample
+    pub fn depth_test(&self, x: i32, y: i32, s: usize, z: f32) -> bool {
+        (0.0..=1.0).contains(&z) && self.closer(z, self.sample_at(x, y, s))
+    }
+
+    // the closest depth of the samples of the pixel
     pub fn pixel_at(&self, x: i32, y: i32) -> f32 {
-        *self.data
-            .get((x + y * self.width) as usize)
-            .unwrap_or(&255f32)
+        (0..self.samples)
+            .map(|s| self.sample_at(x, y, s))
+            .fold(self.far(), |a, b| if self.closer(b, a) { b } else { a })
+    }
+
+    // the closest depth of the pixel from 0 at the near plane to 1 at the far plane, also with reversed-z
+    pub fn depth_at(&self, x: i32, y: i32) -> f32 {
+        let z = self.pixel_at(x, y);
+        if self.reversed { 1.0 - z } else { z }
     }
 
     pub fn set_pixel(&mut self, x: i32, y: i32, v: f32) {
-        self.data[(x + y * self.width) as usize] = v;
+        for s in 0..self.samples {
+            self.set_sample(x, y, s, v);
+        }
+    }
+
+    pub fn sample_at(&self, x: i32, y: i32, s: usize) -> f32 {
+        *self.data
+            .get((x + y * self.width) as usize * self.samples + s)
+            .unwrap_or(&self.far())
+    }
+
+    pub fn set_sample(&mut self, x: i32, y: i32, s: usize, v: f32) {
+        self.data[(x + y * self.width) as usize * self.samples + s] = v;
     }
 
 }
 
+// the colors of the samples of every pixel, for multisampling
+pub struct SampleBuffer {
+    pub width: i32,
+    pub height: i32,
+    pub samples: usize,
+    pub data: Vec<Color>,
+}
+
+impl SampleBuffer {
+    pub fn new(width: i32, height: i32, samples: usize) -> Self {
+        SampleBuffer {
+            width,
+            height,
+            samples,
+            data: vec![Color(0, 0, 0); (width * height) as usize * samples],
+        }
+    }
+
+    pub fn set_sample(&mut self, x: i32, y: i32, s: usize, c: Color) {
+        self.data[(x + y * self.width) as usize * self.samples + s] = c;
+    }
+
+    // average the samples of each pixel into the image
+    pub fn resolve(&self, img: &mut Image) {
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let start = (x + y * self.width) as usize * self.samples;
+                let mut sum = [0usize; 3];
+                for c in &self.data[start..start + self.samples] {
+                    sum[0] += c.0 as usize;
+                    sum[1] += c.1 as usize;
+                    sum[2] += c.2 as usize;
+                }
+                let n = self.samples;
+                img.set_pixel(x, y, Color((sum[0] / n) as u8, (sum[1] / n) as u8, (sum[2] / n) as u8));
+            }
+        }
+    }
+}
+
 impl Into<Image> for ZBuffer {
     fn into(self) -> Image {
+        // stretch the depths that were drawn over the whole gray range, closer is brighter
         let mut img = Image::new(self.width,self.height);
+        let mut range = (f32::MAX, f32::MIN);
         for i in 0..self.width {
             for j in 0..self.height {
-                let c = 255 - self.pixel_at(i, j).round() as u8;
-                img.set_pixel(i,j,Color(c,c,c));
+                if self.pixel_at(i, j) != self.far() {
+                    let d = self.depth_at(i, j);
+                    range = (range.0.min(d), range.1.max(d));
+                }
+            }
+        }
+        let extent = (range.1 - range.0).max(f32::EPSILON);
+        for i in 0..self.width {
+            for j in 0..self.height {
+                if self.pixel_at(i, j) != self.far() {
+                    let c = (255.0 * (1.0 - (self.depth_at(i, j) - range.0) / extent)).round() as u8;
+                    img.set_pixel(i,j,Color(c,c,c));
+                }
             }
         }
         img
     }
 }
 
+#[derive(Clone)]
 pub struct Image {
     pub width: i32,
     pub height: i32,
     pub data: Vec<Color>,
 }
 
+// how texture coordinates outside [0,1] are mapped into the image
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WrapMode {
+    Repeat,
+    Clamp,
+    Mirror,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Filter {
+    Nearest,
+    Bilinear,
+    // bilinear on the two closest levels of the mip chain, blended by the level of detail
+    Trilinear,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Sampler {
+    pub wrap: WrapMode,
+    pub filter: Filter,
+}
+
+impl Default for Sampler {
+    fn default() -> Self {
+        Sampler {
+            wrap: WrapMode::Repeat,
+            filter: Filter::Trilinear,
+        }
+    }
+}
+
+impl Sampler {
+    pub fn new(wrap: WrapMode, filter: Filter) -> Self {
+        Sampler { wrap, filter }
+    }
+
+    // the texel index for an integer coordinate in an image of `size` texels
+    fn wrap(&self, i: i32, size: i32) -> i32 {
+        match self.wrap {
+            WrapMode::Repeat => i.rem_euclid(size),
+            WrapMode::Clamp => i.clamp(0, size - 1),
+            WrapMode::Mirror => {
+                let i = i.rem_euclid(2 * size);
+                if i < size { i } else { 2 * size - 1 - i }
+            }
+        }
+    }
+}
+
+// an image and its chain of halved copies, down to 1x1
+// level 0 is the image itself
+#[derive(Clone)]
+pub struct MipMap {
+    pub levels: Vec<Image>,
+}
+
+impl MipMap {
+    pub fn new(img: &Image) -> Self {
+        let mut levels = vec![img.clone()];
+        while let Some(last) = levels.last() {
+            if last.width <= 1 && last.height <= 1 {
+                break;
+            }
+            let next = last.downsample();
+            levels.push(next);
+        }
+        MipMap { levels }
+    }
+
+    // lod: the level of detail, log2 of the texels covered by a pixel
+    pub fn sample(&self, sampler: &Sampler, u: f32, v: f32, lod: f32) -> Color {
+        if sampler.filter != Filter::Trilinear {
+            return self.levels[0].sample(sampler, u, v);
+        }
+        let bilinear = Sampler::new(sampler.wrap, Filter::Bilinear);
+        let lod = lod.clamp(0.0, (self.levels.len() - 1) as f32);
+        let level = lod.floor() as usize;
+        let fine = self.levels[level].sample(&bilinear, u, v);
+        if level + 1 >= self.levels.len() {
+            return fine;
+        }
+        let coarse = self.levels[level + 1].sample(&bilinear, u, v);
+        let t = lod - level as f32;
+        Color(
+            lerp(fine.0, coarse.0, t),
+            lerp(fine.1, coarse.1, t),
+            lerp(fine.2, coarse.2, t),
+        )
+    }
+}
+
+fn lerp(a: u8, b: u8, t: f32) -> u8 {
+    (a as f32 + (b as f32 - a as f32) * t).round() as u8
+}
+
 unsafe fn struct_to_u8_slice<T>(s: &T) -> &[u8] {
     let data_ptr: *const u8 = mem::transmute(s);
     slice::from_raw_parts(data_ptr, mem::size_of::<T>())
@@ -105,6 +345,60 @@ impl Image {
             .unwrap_or(&Color(0, 0, 0))
     }
 
+    // the color at texture coordinates u,v, which map [0,1] onto the whole image
+    pub fn sample(&self, sampler: &Sampler, u: f32, v: f32) -> Color {
+        if self.width <= 0 || self.height <= 0 {
+            return Color(0, 0, 0);
+        }
+        let texel = |x: i32, y: i32| {
+            self.pixel_at(sampler.wrap(x, self.width), sampler.wrap(y, self.height))
+        };
+        match sampler.filter {
+            Filter::Nearest => texel(
+                (u * self.width as f32).floor() as i32,
+                (v * self.height as f32).floor() as i32,
+            ),
+            Filter::Bilinear | Filter::Trilinear => {
+                // texel centers are at half coordinates
+                let x = u * self.width as f32 - 0.5;
+                let y = v * self.height as f32 - 0.5;
+                let (x0, y0) = (x.floor(), y.floor());
+                let (tx, ty) = (x - x0, y - y0);
+                let (x0, y0) = (x0 as i32, y0 as i32);
+                let c = [texel(x0, y0), texel(x0 + 1, y0), texel(x0, y0 + 1), texel(x0 + 1, y0 + 1)];
+                let channel = |f: fn(&Color) -> u8| {
+                    let top = f(&c[0]) as f32 * (1.0 - tx) + f(&c[1]) as f32 * tx;
+                    let bottom = f(&c[2]) as f32 * (1.0 - tx) + f(&c[3]) as f32 * tx;
+                    (top * (1.0 - ty) + bottom * ty).round() as u8
+                };
+                Color(channel(|c| c.0), channel(|c| c.1), channel(|c| c.2))
+            }
+        }
+    }
+
+    // half the size, each texel the average of (up to) 2x2 texels
+    fn downsample(&self) -> Image {
+        let width = (self.width / 2).max(1);
+        let height = (self.height / 2).max(1);
+        let mut img = Image::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                let mut sum = [0u32; 3];
+                for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                    let c = self.pixel_at(
+                        (2 * x + dx).min(self.width - 1),
+                        (2 * y + dy).min(self.height - 1),
+                    );
+                    sum[0] += c.0 as u32;
+                    sum[1] += c.1 as u32;
+                    sum[2] += c.2 as u32;
+                }
+                img.set_pixel(x, y, Color((sum[0] / 4) as u8, (sum[1] / 4) as u8, (sum[2] / 4) as u8));
+            }
+        }
+        img
+    }
+
     pub fn apply_gamma(self: &mut Image, gamma: f32) {
         for c in self.data.iter_mut() {
             let Color(r, g, b) = *c;
@@ -141,63 +435,132 @@ impl Image {
         res
     }
 
-    pub fn from_raw_vec(v: Vec<u8>) -> Self {
-        #[repr(C, packed)]
-        #[derive(Debug, Copy, Clone)]
-        struct Header {
-            id_length: u8,
-            color_map_type: u8,
-            image_type: u8,
-            c_map_start: u16,
-            c_map_length: u16,
-            c_map_depth: u8,
-            x_offset: u16,
-            y_offset: u16,
-            width: u16,
-            height: u16,
-            pixel_depth: u8,
-            image_descriptor: u8,
-        }
+    // read a tga file: true-color (2) and grayscale (3) images, raw or run-length encoded (10, 11),
+    // of 8, 16, 24 or 32 bits per pixel. The rows are stored bottom to top like the renderer draws them,
+    // whatever the origin of the file
+    pub fn from_raw_vec(v: Vec<u8>) -> Result<Self, ImageError> {
+        let byte = |pos: usize| v.get(pos).copied().ok_or(ImageError::Truncated);
+        let word = |pos: usize| Ok(u16::from_le_bytes([byte(pos)?, byte(pos + 1)?]));
 
-        let mut header: Header = unsafe { mem::zeroed() };
-        let header_size = mem::size_of::<Header>();
-        unsafe {
-            let header_slice =
-                slice::from_raw_parts_mut(&mut header as *mut _ as *mut u8, header_size);
-            let mut r = BufReader::new(&v[..]);
-            r.read_exact(header_slice).unwrap();
-
-            let pixels = vec![ColorA(0, 0, 0, 0); header.width as usize * header.height as usize];
-            let pixels_size = mem::size_of::<ColorA>() * pixels.len();
-            let data_ptr: *mut u8 = mem::transmute(&pixels[..][0]);
-            let pixels_slice = slice::from_raw_parts_mut(data_ptr, pixels_size);
-            r.read_exact(pixels_slice).unwrap();
-
-            let data_correct = {
-                let mut v = vec![Color(0, 0, 0); pixels.len()];
-                for y in 0..header.height {
-                    for x in 0..header.width {
-                        let p = &pixels[y as usize * header.width as usize + x as usize];
-                        v[((header.height - 1) - y) as usize * header.width as usize
-                            + x as usize] = Color(p.0, p.1, p.2);
-                    }
-                }
-                v
-            };
+        let id_length = byte(0)? as usize;
+        let color_map_type = byte(1)?;
+        let image_type = byte(2)?;
+        let c_map_length = word(5)? as usize;
+        let c_map_depth = byte(7)? as usize;
+        let width = word(12)? as usize;
+        let height = word(14)? as usize;
+        let pixel_depth = byte(16)?;
+        let image_descriptor = byte(17)?;
 
-            Image {
-                width: header.width as i32,
-                height: header.height as i32,
-                data: data_correct,
+        let gray = match image_type {
+            2 | 10 => false,
+            3 | 11 => true,
+            t => return Err(ImageError::UnsupportedType(t)),
+        };
+        let bytes = match (gray, pixel_depth) {
+            (false, 16 | 24 | 32) | (true, 8 | 16) => pixel_depth as usize / 8,
+            (_, d) => return Err(ImageError::UnsupportedDepth(d)),
+        };
+        let color = |p: &[u8]| match (gray, bytes) {
+            // the second byte of 16 bit grayscale is the alpha
+            (true, _) => Color(p[0], p[0], p[0]),
+            // 5 bits per channel, blue first
+            (false, 2) => {
+                let p = u16::from_le_bytes([p[0], p[1]]);
+                let channel = |shift: u16| {
+                    let c = ((p >> shift) & 0x1f) as u8;
+                    (c << 3) | (c >> 2)
+                };
+                Color(channel(0), channel(5), channel(10))
+            }
+            (false, _) => Color(p[0], p[1], p[2]),
+        };
+
+        // the id and the color map (unused by these types) come before the pixels
+        let mut pos = 18 + id_length;
+        if color_map_type == 1 {
+            pos += c_map_length * c_map_depth.div_ceil(8);
+        }
+        let pixel = |pos: usize| v.get(pos..pos + bytes).map(&color).ok_or(ImageError::Truncated);
+        let count = width * height;
+        // check the header size against the data before allocating for it
+        let remaining = v.len().saturating_sub(pos);
+        if image_type < 10 && count * bytes > remaining {
+            return Err(ImageError::Truncated);
+        }
+        // every rle packet takes at least one byte
+        let mut pixels: Vec<Color> = Vec::with_capacity(count.min(remaining));
+        while pixels.len() < count {
+            if image_type < 10 {
+                pixels.push(pixel(pos)?);
+                pos += bytes;
+                continue;
+            }
+            // a packet of up to 128 pixels, repeated or raw
+            let header = byte(pos)?;
+            pos += 1;
+            let n = ((header & 0x7f) as usize + 1).min(count - pixels.len());
+            if header & 0x80 != 0 {
+                let c = pixel(pos)?;
+                pos += bytes;
+                pixels.extend(std::iter::repeat_n(c, n));
+            } else {
+                for _ in 0..n {
+                    pixels.push(pixel(pos)?);
+                    pos += bytes;
+                }
             }
         }
+
+        let right_to_left = image_descriptor & 0x10 != 0;
+        let top_to_bottom = image_descriptor & 0x20 != 0;
+        let mut data = vec![Color(0, 0, 0); count];
+        for (i, c) in pixels.into_iter().enumerate() {
+            let (x, y) = (i % width, i / width);
+            let x = if right_to_left { width - 1 - x } else { x };
+            let y = if top_to_bottom { height - 1 - y } else { y };
+            data[x + y * width] = c;
+        }
+        Ok(Image {
+            width: width as i32,
+            height: height as i32,
+            data,
+        })
+    }
+
+    // read a png file, the alpha is dropped
+    pub fn from_png(v: &[u8]) -> Result<Self, ImageError> {
+        let png = lodepng::decode24(v).map_err(|e| ImageError::Png(e.to_string()))?;
+        // png rows are stored top to bottom
+        let data = png
+            .buffer
+            .chunks(png.width)
+            .rev()
+            .flatten()
+            .map(|p| Color(p.b, p.g, p.r))
+            .collect();
+        Ok(Image {
+            width: png.width as i32,
+            height: png.height as i32,
+            data,
+        })
+    }
+
+    // read a png or a tga file, tga files have no signature so anything else is read as tga
+    pub fn decode(v: Vec<u8>) -> Result<Self, ImageError> {
+        if v.starts_with(PNG_SIGNATURE) {
+            Image::from_png(&v)
+        } else {
+            Image::from_raw_vec(v)
+        }
     }
 
-    pub fn from_file(f: String) -> Self {
-        let mut f = File::open(f).unwrap();
+    pub fn from_file(f: String) -> Result<Self, ImageError> {
         let mut buf = Vec::new();
-        f.read_to_end(&mut buf).unwrap();
-        Image::from_raw_vec(buf)
+        File::open(f)
+            .and_then(|mut f| f.read_to_end(&mut buf))
+            .map_err(|e| ImageError::Io(e.to_string()))?;
+        Image::decode(buf)
     }
 
     pub fn write_to_tga(self: &Image, filename: &str) -> io::Result<()> {
@@ -233,3 +596,177 @@ impl Image {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a 2x1 image, black on the left and white on the right
+    fn black_white() -> Image {
+        let mut img = Image::new(2, 1);
+        img.set_pixel(1, 0, Color(255, 255, 255));
+        img
+    }
+
+    #[test]
+    fn test_sampler_wrap() {
+        let img = black_white();
+        let nearest = |wrap| img.sample(&Sampler::new(wrap, Filter::Nearest), 1.25, 0.5).0;
+        assert_eq!(nearest(WrapMode::Repeat), 0);
+        assert_eq!(nearest(WrapMode::Clamp), 255);
+        assert_eq!(nearest(WrapMode::Mirror), 255);
+    }
+
+    #[test]
+    fn test_sampler_bilinear() {
+        let img = black_white();
+        let c = img.sample(&Sampler::new(WrapMode::Clamp, Filter::Bilinear), 0.5, 0.5);
+        assert_eq!(c.0, 128);
+    }
+
+    #[test]
+    fn test_mipmap() {
+        let mut img = Image::new(4, 2);
+        for x in 0..4 {
+            img.set_pixel(x, 0, Color(200, 100, 0));
+        }
+        let mips = MipMap::new(&img);
+        let sizes: Vec<(i32, i32)> = mips.levels.iter().map(|l| (l.width, l.height)).collect();
+        assert_eq!(sizes, vec![(4, 2), (2, 1), (1, 1)]);
+        // the last level is the average of the image
+        let c = mips.sample(&Sampler::default(), 0.3, 0.7, 10.0);
+        assert_eq!((c.0, c.1, c.2), (100, 50, 0));
+    }
+
+    #[test]
+    fn test_multisample_resolve() {
+        let mut z_buffer = ZBuffer::with_samples(2, 1, 4);
+        let mut samples = SampleBuffer::new(2, 1, 4);
+        // half of the samples of the first pixel are covered
+        for s in 0..2 {
+            z_buffer.set_sample(0, 0, s, 0.5);
+            samples.set_sample(0, 0, s, Color(200, 100, 0));
+        }
+        assert_eq!(z_buffer.pixel_at(0, 0), 0.5);
+        assert_eq!(z_buffer.sample_at(0, 0, 3), 1.0);
+        let mut img = Image::new(2, 1);
+        samples.resolve(&mut img);
+        let c = img.pixel_at(0, 0);
+        assert_eq!((c.0, c.1, c.2), (100, 50, 0));
+        let c = img.pixel_at(1, 0);
+        assert_eq!((c.0, c.1, c.2), (0, 0, 0));
+    }
+
+    #[test]
+    fn test_depth_image() {
+        for reversed in [false, true] {
+            let mut z_buffer = ZBuffer::new(3, 1).with_reversed_z(reversed);
+            let (near, far) = if reversed { (0.9, 0.8) } else { (0.1, 0.2) };
+            z_buffer.set_pixel(0, 0, near);
+            z_buffer.set_pixel(1, 0, far);
+            let step = if reversed { -0.05 } else { 0.05 };
+            assert!(z_buffer.depth_test(0, 0, 0, near - step));
+            assert!(!z_buffer.depth_test(1, 0, 0, far + step));
+            // the drawn range is stretched over the grays, nothing drawn is black
+            let img: Image = z_buffer.into();
+            let grays: Vec<u8> = (0..3).map(|x| img.pixel_at(x, 0).0).collect();
+            assert_eq!(grays, vec![255, 0, 0]);
+        }
+    }
+
+    // a tga file of 2x2 pixels with the given type, depth, descriptor and pixel data
+    fn tga(image_type: u8, depth: u8, descriptor: u8, pixels: &[u8]) -> Vec<u8> {
+        // an id field of 3 bytes
+        let mut v = vec![3, 0, image_type, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 0, 2, 0, depth, descriptor, b'i', b'd', b'!'];
+        v.extend_from_slice(pixels);
+        v
+    }
+
+    fn colors(img: &Image) -> Vec<(u8, u8, u8)> {
+        img.data.iter().map(|c| (c.0, c.1, c.2)).collect()
+    }
+
+    #[test]
+    fn test_tga_round_trip() {
+        let mut img = Image::new(5, 3);
+        for (i, c) in img.data.iter_mut().enumerate() {
+            *c = Color(i as u8 * 10, 255 - i as u8, (i * i) as u8);
+        }
+        let file = std::env::temp_dir().join(format!("rusterizer_round_trip_{}.tga", std::process::id()));
+        img.write_to_tga(file.to_str().unwrap()).unwrap();
+        let read = Image::from_file(file.to_string_lossy().into_owned());
+        std::fs::remove_file(&file).unwrap();
+        let read = read.unwrap();
+        assert_eq!((read.width, read.height), (5, 3));
+        assert_eq!(colors(&read), colors(&img));
+    }
+
+    #[test]
+    fn test_tga_types() {
+        let expected = vec![(1, 2, 3), (4, 5, 6), (7, 8, 9), (10, 11, 12)];
+        let raw: Vec<u8> = (1..=12).collect();
+        let rgb = Image::from_raw_vec(tga(2, 24, 0, &raw)).unwrap();
+        assert_eq!(colors(&rgb), expected);
+        // the same pixels with alpha, run-length encoded as a raw packet of 4
+        let mut rle = vec![0x03];
+        for p in raw.chunks(3) {
+            rle.extend_from_slice(p);
+            rle.push(255);
+        }
+        assert_eq!(colors(&Image::from_raw_vec(tga(10, 32, 8, &rle)).unwrap()), expected);
+        // a repeated packet of 3 and a raw packet of 1, grayscale
+        let gray = Image::from_raw_vec(tga(11, 8, 0, &[0x82, 50, 0x00, 200])).unwrap();
+        assert_eq!(colors(&gray), vec![(50, 50, 50), (50, 50, 50), (50, 50, 50), (200, 200, 200)]);
+        let gray = Image::from_raw_vec(tga(3, 16, 0, &[1, 255, 2, 255, 3, 255, 4, 255])).unwrap();
+        assert_eq!(colors(&gray)[3], (4, 4, 4));
+        // 5 bits per channel: blue, green, red, white
+        let high = Image::from_raw_vec(tga(2, 16, 0, &[0x1f, 0x00, 0xe0, 0x03, 0x00, 0x7c, 0xff, 0x7f])).unwrap();
+        assert_eq!(colors(&high), vec![(255, 0, 0), (0, 255, 0), (0, 0, 255), (255, 255, 255)]);
+    }
+
+    #[test]
+    fn test_tga_origin() {
+        let gray = [1, 2, 3, 4];
+        let pixels = |descriptor| colors(&Image::from_raw_vec(tga(3, 8, descriptor, &gray)).unwrap());
+        let g = |x: u8| (x, x, x);
+        assert_eq!(pixels(0x00), vec![g(1), g(2), g(3), g(4)]);
+        // right to left, top to bottom and both
+        assert_eq!(pixels(0x10), vec![g(2), g(1), g(4), g(3)]);
+        assert_eq!(pixels(0x20), vec![g(3), g(4), g(1), g(2)]);
+        assert_eq!(pixels(0x30), vec![g(4), g(3), g(2), g(1)]);
+    }
+
+    #[test]
+    fn test_tga_errors() {
+        assert_eq!(Image::from_raw_vec(vec![0; 10]).err(), Some(ImageError::Truncated));
+        assert_eq!(Image::from_raw_vec(tga(2, 24, 0, &[0; 11])).err(), Some(ImageError::Truncated));
+        assert_eq!(Image::from_raw_vec(tga(10, 24, 0, &[0x83, 1, 2])).err(), Some(ImageError::Truncated));
+        // a 65535x65535 header in a 20 byte file
+        for image_type in [2, 10] {
+            let mut huge = vec![0, 0, image_type, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff, 0xff, 0xff, 24, 0, 0x80, 1];
+            assert_eq!(Image::from_raw_vec(huge.clone()).err(), Some(ImageError::Truncated));
+            huge.truncate(18);
+            assert_eq!(Image::from_raw_vec(huge).err(), Some(ImageError::Truncated));
+        }
+        assert_eq!(Image::from_raw_vec(tga(1, 8, 0, &[0; 4])).err(), Some(ImageError::UnsupportedType(1)));
+        assert_eq!(Image::from_raw_vec(tga(3, 24, 0, &[0; 12])).err(), Some(ImageError::UnsupportedDepth(24)));
+        assert!(matches!(Image::from_file("does/not/exist.tga".into()), Err(ImageError::Io(_))));
+    }
+
+    #[test]
+    fn test_png() {
+        // 2x2, top row red and green, bottom row blue and white
+        let rgb = [[255u8, 0, 0], [0, 255, 0], [0, 0, 255], [255, 255, 255]];
+        let png = lodepng::encode_memory(&rgb.concat(), 2, 2, lodepng::ColorType::RGB, 8).unwrap();
+        let img = Image::decode(png).unwrap();
+        assert_eq!((img.width, img.height), (2, 2));
+        // bottom row first, blue first in each pixel
+        assert_eq!(colors(&img), vec![(255, 0, 0), (255, 255, 255), (0, 0, 255), (0, 255, 0)]);
+        // the rest are tga files
+        let raw: Vec<u8> = (1..=12).collect();
+        assert_eq!(colors(&Image::decode(tga(2, 24, 0, &raw)).unwrap())[0], (1, 2, 3));
+        let mut broken = PNG_SIGNATURE.to_vec();
+        broken.extend_from_slice(&[0; 8]);
+        assert!(matches!(Image::decode(broken), Err(ImageError::Png(_))));
+    }
+}
@@ -4,18 +4,14 @@ use crate::{
     la::MatrixI,
 };
 
-const Z_DEPTH:f32 = 255.0;
-
-
+// from normalized device coordinates to pixels, the depth stays in 0..1
 pub fn get_viewport_matrix(w:i32, h:i32) -> Matrix<4,4> {
     let mut viewport = Matrix::identity();
     viewport[0][0] = w as f32/ 2.;
     viewport[1][1] = h as f32/ 2.;
-    viewport[2][2] = Z_DEPTH / 2.;
 
     viewport[0][3] = (w-1) as f32/ 2.;
 	viewport[1][3] = (h-1) as f32/ 2.;
-	viewport[2][3] = Z_DEPTH / 2.;
 
     viewport
 }
@@ -43,20 +39,122 @@ pub fn calculate_lookat_matrix(position: Vec3f, view : Vec3f, up_vector: Vec3f)
 }
 
 
-pub fn calculate_prespective_projection(fovy_in_radians:f32,aspect:f32, z_near:f32, z_far:f32, zoom: f32) -> Matrix<4,4>{
+// the depth goes from 0 at z_near to 1 at z_far, from 1 to 0 with reversed_z
+pub fn calculate_prespective_projection(fovy_in_radians:f32,aspect:f32, z_near:f32, z_far:f32, zoom: f32, reversed_z: bool) -> Matrix<4,4>{
     let mut projection = Matrix::identity();
     let theta = fovy_in_radians/2f32;
     let zoom_tan_theta = theta.tan() / zoom;
     projection[0][0] = 1.0/(aspect*zoom_tan_theta);
     projection[1][1] = 1.0 / zoom_tan_theta;
-    projection[2][2] = (z_far+z_near)/(z_near-z_far);
-
-    projection[2][3] = 2f32*z_far*z_near/(z_near-z_far);
+    // w is 1 - z, the depth is mapped for that
+    if reversed_z {
+        projection[2][2] = (1.0+z_near)/(z_far-z_near);
+        projection[2][3] = (1.0+z_near)*z_far/(z_far-z_near);
+    } else {
+        projection[2][2] = (1.0+z_far)/(z_near-z_far);
+        projection[2][3] = (1.0+z_far)*z_near/(z_near-z_far);
+    }
     projection[3][2] = -1f32;
     projection
     // Matrix::identity()
 }
 
+// the depth goes from 0 at z_near to 1 at z_far
+pub fn calculate_orthographic_projection(left: f32, right: f32, bottom: f32, top: f32, z_near: f32, z_far: f32) -> Matrix<4,4> {
+    let mut projection = Matrix::identity();
+    projection[0][0] = 2.0 / (right - left);
+    projection[1][1] = 2.0 / (top - bottom);
+    projection[2][2] = -1.0 / (z_far - z_near);
+
+    projection[0][3] = -(right + left) / (right - left);
+    projection[1][3] = -(top + bottom) / (top - bottom);
+    projection[2][3] = -z_near / (z_far - z_near);
+    projection
+}
+
+// model matrices, to place an object in the world
+
+pub fn translation_matrix(offset: Vec3f) -> Matrix<4,4> {
+    let mut m = Matrix::identity();
+    m[0][3] = offset.0;
+    m[1][3] = offset.1;
+    m[2][3] = offset.2;
+    m
+}
+
+pub fn scale_matrix(scale: Vec3f) -> Matrix<4,4> {
+    let mut m = Matrix::identity();
+    m[0][0] = scale.0;
+    m[1][1] = scale.1;
+    m[2][2] = scale.2;
+    m
+}
+
+// rotate counterclockwise by the angle around the axis (Rodrigues' rotation formula)
+pub fn rotation_matrix(axis: Vec3f, angle_in_radians: f32) -> Matrix<4,4> {
+    let Vec3f(x, y, z) = axis.normalize();
+    let (s, c) = angle_in_radians.sin_cos();
+    let t = 1.0 - c;
+    [
+        [t * x * x + c, t * x * y - s * z, t * x * z + s * y, 0.0],
+        [t * x * y + s * z, t * y * y + c, t * y * z - s * x, 0.0],
+        [t * x * z - s * y, t * y * z + s * x, t * z * z + c, 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ]
+}
+
+// the pose of an object in the world: scaled, then rotated around x, y and z (in radians), then translated
+#[derive(Clone, Copy, Debug)]
+pub struct Transform {
+    pub translation: Vec3f,
+    pub rotation: Vec3f,
+    pub scale: Vec3f,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self {
+            translation: Vec3f(0.0, 0.0, 0.0),
+            rotation: Vec3f(0.0, 0.0, 0.0),
+            scale: Vec3f(1.0, 1.0, 1.0),
+        }
+    }
+}
+
+impl Transform {
+    pub fn new(translation: Vec3f, rotation: Vec3f, scale: Vec3f) -> Self {
+        Self { translation, rotation, scale }
+    }
+
+    // the model matrix, object space to world space
+    pub fn matrix(&self) -> Matrix<4,4> {
+        let rotation = rotation_matrix(Vec3f(0.0, 0.0, 1.0), self.rotation.2)
+            .mul(&rotation_matrix(Vec3f(0.0, 1.0, 0.0), self.rotation.1))
+            .mul(&rotation_matrix(Vec3f(1.0, 0.0, 0.0), self.rotation.0));
+        translation_matrix(self.translation)
+            .mul(&rotation)
+            .mul(&scale_matrix(self.scale))
+    }
+}
+
+// transform a point by an affine matrix
+pub fn transform_point(m: &Matrix<4,4>, p: &Vec3f) -> Vec3f {
+    Vec3f(
+        m[0][0] * p.0 + m[0][1] * p.1 + m[0][2] * p.2 + m[0][3],
+        m[1][0] * p.0 + m[1][1] * p.1 + m[1][2] * p.2 + m[1][3],
+        m[2][0] * p.0 + m[2][1] * p.1 + m[2][2] * p.2 + m[2][3],
+    )
+}
+
+// transform a direction by an affine matrix, the translation is ignored
+pub fn transform_vector(m: &Matrix<4,4>, v: &Vec3f) -> Vec3f {
+    Vec3f(
+        m[0][0] * v.0 + m[0][1] * v.1 + m[0][2] * v.2,
+        m[1][0] * v.0 + m[1][1] * v.1 + m[1][2] * v.2,
+        m[2][0] * v.0 + m[2][1] * v.1 + m[2][2] * v.2,
+    )
+}
+
 // calculate the bary centric coordniates 
 // point p is in tri(a,b,c)
 // get the Vec3f(u,v,w) where ua+vb+wc = p && u+v+w=1
@@ -70,3 +168,25 @@ pub fn barycentric(a: &Vec3f, b: &Vec3f, c: &Vec3f, p: (f32, f32)) -> Vec3f {
     )
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn depth(projection: &Matrix<4,4>, z: f32) -> f32 {
+        let p = projection.mul(&[[0.0], [0.0], [z], [1.0]]);
+        p[2][0] / p[3][0]
+    }
+
+    #[test]
+    fn test_depth_range() {
+        let projection = calculate_prespective_projection(1.0, 1.0, 0.1, 100.0, 1.0, false);
+        assert!(depth(&projection, -0.1).abs() < 1e-5);
+        assert!((depth(&projection, -100.0) - 1.0).abs() < 1e-5);
+        let reversed = calculate_prespective_projection(1.0, 1.0, 0.1, 100.0, 1.0, true);
+        assert!((depth(&reversed, -0.1) - 1.0).abs() < 1e-5);
+        assert!(depth(&reversed, -100.0).abs() < 1e-5);
+        let ortho = calculate_orthographic_projection(-1.0, 1.0, -1.0, 1.0, 1.0, 3.0);
+        assert!(depth(&ortho, -1.0).abs() < 1e-6);
+        assert!((depth(&ortho, -3.0) - 1.0).abs() < 1e-6);
+    }
+}
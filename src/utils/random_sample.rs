@@ -1,11 +1,17 @@
 use crate::{
-    la::{Vec3f},
+    la::{Vec3f,MatrixI},
+    transform::{rotation_matrix,scale_matrix,translation_matrix},
+    utils::degrees_to_radians,
     raytracing::Ray,
-    raytracing::{Hittable},
-    raytracing::materials::{Dielectric,Lambertian,Material,Metal},
-    models::objects::Sphere, 
+    raytracing::{Hittable,Environment,Instance},
+    raytracing::materials::{Dielectric,DiffuseLight,Lambertian,Material,Metal},
+    raytracing::textures::CheckerTexture,
+    models::objects::{Quad,Sphere,TriangleMesh},
+    models::{load_materials, ObjError, Wavefront},
     scene::{RayTracingScene,SceneTrait},
 };
+use std::f32::consts::PI;
+use std::path::Path;
 use std::sync::Arc;
 use rand::{random, Rng};
 
@@ -51,6 +57,18 @@ pub fn random_unit_vector() -> Vec3f {
     }
 }
 
+// a random direction inside the cone (around +z) that a sphere of `radius` at
+// `distance_squared` covers, uniform over the solid angle
+pub fn random_to_sphere(radius: f32, distance_squared: f32) -> Vec3f {
+    let r1 = random::<f32>();
+    let r2 = random::<f32>();
+    let cos_theta_max = (1.0 - radius * radius / distance_squared).max(0.0).sqrt();
+    let z = 1.0 + r2 * (cos_theta_max - 1.0);
+    let phi = 2.0 * PI * r1;
+    let sin_theta = (1.0 - z * z).max(0.0).sqrt();
+    Vec3f(phi.cos() * sin_theta, phi.sin() * sin_theta, z)
+}
+
 pub fn random_hemisphere_vector(normal: &Vec3f) -> Vec3f {
     let in_unit_sphere = random_in_unit_sphere();
     if in_unit_sphere.dot(normal) > 0.0 {
@@ -61,8 +79,21 @@ pub fn random_hemisphere_vector(normal: &Vec3f) -> Vec3f {
 }
 
 pub fn ray_color(ray: &Ray, world: &RayTracingScene, depth: usize) -> Vec3f {
-    const WHITE: Vec3f= Vec3f(1.0, 1.0, 1.0);
-    const SKY_BLUE: Vec3f = Vec3f(0.5, 0.7, 1.0);
+    ray_color_mis(ray, world, depth, None)
+}
+
+// the power heuristic of multiple importance sampling, the weight of the strategy with pdf `a`
+fn power_heuristic(a: f32, b: f32) -> f32 {
+    if a <= 0.0 {
+        return 0.0;
+    }
+    a * a / (a * a + b * b)
+}
+
+// path tracing with next event estimation: at every non specular hit one direction towards the
+// registered lights is sampled besides the bsdf direction, both are weighted by the power heuristic
+// `bsdf_pdf` is the pdf of the bsdf sample that produced `ray`, None for camera and specular rays
+fn ray_color_mis(ray: &Ray, world: &RayTracingScene, depth: usize, bsdf_pdf: Option<f32>) -> Vec3f {
     // If we've exceeded the ray bounce limit, no more light is gathered.
     if depth == 0 {
         return Vec3f(0.0, 0.0, 0.0);
@@ -71,15 +102,42 @@ pub fn ray_color(ray: &Ray, world: &RayTracingScene, depth: usize) -> Vec3f {
     if let Some(mut rec) = world.hit(0.001, f32::MAX, ray) {
         let material = rec.material.unwrap();
         rec.material = None;
-        return if let Some(scattered) = material.scatter(&ray, &rec) {
-            scattered.attenuation * ray_color(&scattered.ray, &world, depth - 1)
-        } else {
-            Vec3f(0.0, 0.0, 0.0)
+        let mut emitted = material.emitted(&rec);
+        // the light sampling of the previous hit could also have found this emitter
+        if let Some(pdf) = bsdf_pdf {
+            emitted = emitted * power_heuristic(pdf, world.light_pdf(&ray.origin, &ray.direction));
+        }
+        let scattered = match material.scatter(ray, &rec) {
+            Some(scattered) => scattered,
+            None => return emitted,
+        };
+        let pdf = match scattered.pdf {
+            Some(pdf) => pdf,
+            None => {
+                return emitted
+                    + scattered.attenuation * ray_color_mis(&scattered.ray, world, depth - 1, None);
+            }
         };
+
+        let mut direct = Vec3f::zeroed();
+        if let Some(direction) = world.sample_light(&rec.place) {
+            let light_pdf = world.light_pdf(&rec.place, &direction);
+            let f = material.eval(ray, &rec, &direction);
+            if light_pdf > 0.0 && !f.near_zero() {
+                let shadow_ray = Ray::new(rec.place, direction);
+                if let Some(light_rec) = world.hit(0.001, f32::MAX, &shadow_ray) {
+                    let light_emitted = light_rec.material.as_ref().unwrap().emitted(&light_rec);
+                    let weight = power_heuristic(light_pdf, material.pdf(ray, &rec, &direction));
+                    direct = f * light_emitted * (weight / light_pdf);
+                }
+            }
+        }
+
+        return emitted
+            + direct
+            + scattered.attenuation * ray_color_mis(&scattered.ray, world, depth - 1, Some(pdf));
     }
-    let unit_direction = ray.direction.normalize();
-    let t = 0.5 * (unit_direction.y() + 1.0);
-    (1.0 - t) * WHITE + t * SKY_BLUE
+    world.environment.color(&ray.direction)
 }
 
 pub fn random_scene() -> RayTracingScene {
@@ -148,5 +206,125 @@ pub fn random_scene() -> RayTracingScene {
         material: material3,
     }));
 
+    world.build_bvh();
+    world
+}
+
+// a wavefront model (e.g. res/african_head/model.obj) standing on a large checkered ground sphere,
+// with two smaller instances of the same mesh behind it turned towards the center
+pub fn mesh_scene(file: String, material: Arc<dyn Material>) -> Result<RayTracingScene, ObjError> {
+    let mut world = RayTracingScene::new();
+    let checker = CheckerTexture::from_colors(Vec3f(0.2, 0.3, 0.1), Vec3f(0.9, 0.9, 0.9), 0.5);
+    let ground_material = Arc::new(Lambertian::with_texture(Arc::new(checker)));
+    world.add(Box::new(Sphere {
+        center: Vec3f(0.0, -1001.0, 0.0),
+        radius: 1000.0,
+        material: ground_material,
+    }));
+    // the mtl materials of the model, read next to it
+    let dir = Path::new(&file).parent().unwrap_or_else(|| Path::new("")).to_owned();
+    let model = Wavefront::parse_file(file)?;
+    let materials = load_materials(&model, &dir)?
+        .iter()
+        .map(|m| m.raytracing_material(&dir))
+        .collect();
+    let mesh: Arc<dyn Hittable> = Arc::new(TriangleMesh::with_materials(model, materials, material));
+    for (x, angle) in [(-1.5f32, 45f32), (1.5, -45.0)] {
+        let transform = translation_matrix(Vec3f(x, -0.4, -1.5))
+            .mul(&rotation_matrix(Vec3f(0.0, 1.0, 0.0), degrees_to_radians(angle)))
+            .mul(&scale_matrix(Vec3f(0.6, 0.6, 0.6)));
+        world.add(Box::new(Instance::new(mesh.clone(), transform)));
+    }
+    world.add(Box::new(mesh));
+    world.build_bvh();
+    Ok(world)
+}
+
+// the cornell box, closed on every side so that the only light comes from the quad on the ceiling
+// the box spans (0, 0, -300) to (555, 555, 555), look from inside near z = -300 towards z = 555
+pub fn cornell_box() -> RayTracingScene {
+    let mut world = RayTracingScene::new();
+    world.environment = Environment::Solid(Vec3f::zeroed());
+    let red: Arc<dyn Material> = Arc::new(Lambertian::new(Vec3f(0.65, 0.05, 0.05)));
+    let white: Arc<dyn Material> = Arc::new(Lambertian::new(Vec3f(0.73, 0.73, 0.73)));
+    let green: Arc<dyn Material> = Arc::new(Lambertian::new(Vec3f(0.12, 0.45, 0.15)));
+    let light: Arc<dyn Material> = Arc::new(DiffuseLight::new(Vec3f(15.0, 15.0, 15.0)));
+
+    // walls
+    let depth = Vec3f(0.0, 0.0, 855.0);
+    world.add(Box::new(Quad::new(Vec3f(555.0, 0.0, -300.0), Vec3f(0.0, 555.0, 0.0), depth, green)));
+    world.add(Box::new(Quad::new(Vec3f(0.0, 0.0, -300.0), Vec3f(0.0, 555.0, 0.0), depth, red)));
+    world.add(Box::new(Quad::new(Vec3f(0.0, 0.0, -300.0), Vec3f(555.0, 0.0, 0.0), depth, white.clone())));
+    world.add(Box::new(Quad::new(Vec3f(0.0, 555.0, -300.0), Vec3f(555.0, 0.0, 0.0), depth, white.clone())));
+    world.add(Box::new(Quad::new(Vec3f(0.0, 0.0, 555.0), Vec3f(555.0, 0.0, 0.0), Vec3f(0.0, 555.0, 0.0), white.clone())));
+    world.add(Box::new(Quad::new(Vec3f(0.0, 0.0, -300.0), Vec3f(555.0, 0.0, 0.0), Vec3f(0.0, 555.0, 0.0), white.clone())));
+
+    // the light, just below the ceiling
+    world.add_light(Arc::new(Quad::new(Vec3f(343.0, 554.0, 332.0), Vec3f(-130.0, 0.0, 0.0), Vec3f(0.0, 0.0, -105.0), light)));
+
+    world.add(Box::new(Sphere {
+        center: Vec3f(190.0, 90.0, 190.0),
+        radius: 90.0,
+        material: Arc::new(Dielectric::new(1.5)),
+    }));
+    world.add(Box::new(Sphere {
+        center: Vec3f(370.0, 90.0, 370.0),
+        radius: 90.0,
+        material: white,
+    }));
+
+    world.build_bvh();
     world
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLES: usize = 200_000;
+
+    #[test]
+    fn test_power_heuristic() {
+        for (a, b) in [(1.0, 1.0), (0.5, 2.0), (10.0, 0.1), (3.0, 0.0)] {
+            let sum = power_heuristic(a, b) + power_heuristic(b, a);
+            assert!((sum - 1.0).abs() < 1e-6, "{} {}", a, b);
+        }
+        assert_eq!(power_heuristic(0.0, 1.0), 0.0);
+        // the strategy with the larger pdf gets the larger weight
+        assert!(power_heuristic(2.0, 1.0) > 0.5);
+    }
+
+    // the pdf of a light integrated over all the directions from origin, and the solid angle the
+    // light covers measured with the directions it samples, relative to the one measured uniformly
+    fn pdf_integrals(light: &dyn Hittable, origin: &Vec3f) -> (f32, f32) {
+        let (mut integral, mut covered) = (0.0, 0);
+        for _ in 0..SAMPLES {
+            let pdf = light.pdf_value(origin, &random_unit_vector());
+            integral += pdf;
+            covered += (pdf > 0.0) as usize;
+        }
+        let integral = integral * 4.0 * PI / SAMPLES as f32;
+        let solid_angle = covered as f32 * 4.0 * PI / SAMPLES as f32;
+        // every sampled direction reaches the light, and 1/pdf averages to its solid angle
+        let mut inverse = 0.0;
+        for _ in 0..SAMPLES {
+            let pdf = light.pdf_value(origin, &light.random_direction(origin));
+            assert!(pdf > 0.0);
+            inverse += 1.0 / pdf;
+        }
+        (integral, inverse / SAMPLES as f32 / solid_angle)
+    }
+
+    #[test]
+    fn test_light_pdfs() {
+        let light: Arc<dyn Material> = Arc::new(DiffuseLight::new(Vec3f(1.0, 1.0, 1.0)));
+        let quad = Quad::new(Vec3f(-1.0, 2.0, -0.5), Vec3f(2.0, 0.0, 0.0), Vec3f(0.0, 0.5, 1.5), light.clone());
+        let sphere = Sphere { center: Vec3f(0.5, 1.0, -2.0), radius: 0.8, material: light };
+        let origin = Vec3f(0.0, 0.0, 0.0);
+        for (name, object) in [("quad", &quad as &dyn Hittable), ("sphere", &sphere)] {
+            let (integral, ratio) = pdf_integrals(object, &origin);
+            assert!((integral - 1.0).abs() < 0.05, "{}: {}", name, integral);
+            assert!((ratio - 1.0).abs() < 0.05, "{}: {}", name, ratio);
+        }
+    }
+}
@@ -27,3 +27,16 @@ pub fn other_refract(uv: Vec3f, n: Vec3f, etai_over_etat: f32) -> Vec3f {
     r_out_parallel + r_out_perp
 }
 
+
+// an orthonormal basis (u, v, w) whose w axis is the given direction
+pub fn orthonormal_basis(direction: &Vec3f) -> (Vec3f, Vec3f, Vec3f) {
+    let w = direction.normalize();
+    let a = if w.x().abs() > 0.9 {
+        Vec3f(0.0, 1.0, 0.0)
+    } else {
+        Vec3f(1.0, 0.0, 0.0)
+    };
+    let v = w.cross(&a).normalize();
+    let u = w.cross(&v);
+    (u, v, w)
+}
@@ -7,11 +7,13 @@ use yew::services::{ConsoleService, FetchService};
 use yew::{html, Component, Html, NodeRef};
 
 use crate::la::{Matrix, MatrixI, Vec3f};
-use crate::models::{MeshObject, Wavefront};
-use crate::shader::{BasicShader, LightShader, Shader, ShaderConf};
-use crate::render::triangle;
+use crate::models::{MeshObject, NormalSpace, PbrMaterial, PbrParam, Wavefront};
+use crate::shader::{LightShader, ShaderConf};
+use crate::render::{apply_occlusion, draw_face, draw_serial, geometry_buffer, shadow_maps, ssao, FrameBuffer, SceneShaders};
+use crate::scene::Light;
+use crate::transform::get_viewport_matrix;
 
-use crate::tga::{Image,ZBuffer};
+use crate::tga::Image;
 use crate::camera::{self, CameraTrait, PerspectiveCamera, Projectable};
 
 // use crate::transform::{get_prespective_projection};
@@ -30,7 +32,15 @@ pub enum Msg {
     Txt,
     Zbuff,
     Norm,
+    NormalSpace,
     Occl,
+    Persp,
+    Shadow,
+    Pbr,
+    Ssao,
+    Msaa,
+    ReversedZ,
+    Light,
     RotationStarted(i32, i32),
     RotationEnded,
     MoveStarted(i32, i32),
@@ -48,6 +58,8 @@ pub enum ModelType {
 pub struct Model<T: CameraTrait + Projectable> where Model<T>: yew::Component {
     conf: ShaderConf,
     zbuff: bool,
+    // samples per pixel for multisampling, 1 is off
+    samples: usize,
     node_ref: NodeRef,
     props: (),
     link: yew::ComponentLink<Self>,
@@ -55,7 +67,11 @@ pub struct Model<T: CameraTrait + Projectable> where Model<T>: yew::Component {
     texture: Option<Image>,
     wavefront: Option<Wavefront>,
     normals: Option<Image>,
+    // how the loaded normal map encodes the normals
+    normal_space: NormalSpace,
     model: Option<MeshObject>,
+    // the lights in view space, they follow the camera
+    lights: Vec<Light>,
     model_type: ModelType,
     camera: T,
     rotation_start: Option<(i32, i32, Vec3f)>,
@@ -67,43 +83,40 @@ impl<T> Model<T> where T:CameraTrait + Projectable,Model<T>: yew::Component, <Mo
     fn render(&mut self) {
         let width: i32 = WIDTH as i32;
         let height: i32 = HEIGHT as i32;
-        let mut out_texture = Image::new(width, height);
-        let mut z_buffer = ZBuffer::new(width, height);
-        let mut light_texture = Image::new(width, height);
-
         let camera = &self.camera;
         let lookat_m = camera.get_lookat().clone();
-        let lookat_mi = lookat_m.inverse().transpose();
-        // let light_dir: Vec3f = get_prespective_projection(5f32).mul(&lookat_m).mul(&Vec3f(1.0, -0.0, 0.5).embed::<4>(1f32)).into();
-        let light_dir: Vec3f = Vec3f(1.0, -0.0, 0.5).normalize();
+        let viewport = get_viewport_matrix(width, height);
+        let lights = self.lights.clone();
         let model = self.model.as_ref().unwrap();
-        let mut shader = BasicShader {
+        let shadow_maps = if self.conf.shadows {
+            shadow_maps(&[model], &lookat_m, &lights)
+        } else {
+            lights.iter().map(|_| None).collect()
+        };
+        let project_m = *camera.get_projection();
+        // the browser has no threads to draw tiles in parallel
+        let mut frame = FrameBuffer::new(width, height, self.samples, camera.reversed_z());
+        let shaders = SceneShaders {
             conf: self.conf.clone(),
-            normal_face_vec: None,
-            light_dir,
+            objects: vec![model],
+            lights,
+            shadow_maps,
             lookat_m,
-            lookat_mi,
-            model,
-            out_texture: &mut out_texture,
-            z_buffer: &mut z_buffer,
-            varying_uv: Matrix::zeroed(),
-            varying_xy: Matrix::zeroed(),
-            vertices: [Vec3f::zeroed(); 3],
-            light_texture: &mut light_texture,
-            project_m: camera.get_projection().clone(),
+            project_m,
         };
-
-        for f in 0..model.num_faces() {
-            let mut vertices = [Vec3f::zeroed(), Vec3f::zeroed(), Vec3f::zeroed()];
-            for v in 0..3 {
-                vertices[v] = shader.vertex(f, v);
-            }
-            triangle(&vertices[0], &vertices[1], &vertices[2], &mut shader);
-        }
+        draw_serial(&mut frame, &[model.num_faces()], &viewport, &shaders);
+        frame.resolve();
+        let FrameBuffer { mut out_texture, mut light_texture, mut z_buffer, .. } = frame;
 
         let light_model = MeshObject::screen_texture_model();
 
-        if self.conf.occlusion {
+        if self.conf.occlusion && self.conf.ssao {
+            let gbuffer = geometry_buffer(&[model], &lookat_m, &project_m, &viewport, width, height, camera.reversed_z());
+            let occlusion = ssao(&gbuffer, &project_m, &viewport, self.conf.ssao_radius);
+            // the pbr shader lights the image itself
+            let light = if self.conf.pbr { None } else { Some(&light_texture) };
+            apply_occlusion(&mut out_texture, light, &occlusion);
+        } else if self.conf.occlusion {
             let mut occl_texture = Image::new(width, height);
             let mut light_shader = LightShader {
                 conf: ShaderConf::new(),
@@ -117,15 +130,14 @@ impl<T> Model<T> where T:CameraTrait + Projectable,Model<T>: yew::Component, <Mo
             };
 
             for f in 0..light_model.num_faces() {
-                let mut vertices = [Vec3f::zeroed(), Vec3f::zeroed(), Vec3f::zeroed()];
-                for v in 0..3 {
-                    vertices[v] = light_shader.vertex(f, v);
-                }
-                triangle(&vertices[0], &vertices[1], &vertices[2], &mut light_shader);
+                draw_face(f, &viewport, &mut light_shader);
             }
         }
 
-        out_texture.apply_gamma(1.5);
+        // the pbr shader already tone-maps and gamma corrects
+        if !self.conf.pbr {
+            out_texture.apply_gamma(1.5);
+        }
 
         let canvas = self.node_ref.cast::<HtmlCanvasElement>().unwrap();
         // canvas.set_width(WIDTH);
@@ -146,7 +158,25 @@ impl<T> Model<T> where T:CameraTrait + Projectable,Model<T>: yew::Component, <Mo
             self.wavefront.take().unwrap(),
             self.normals.take().unwrap(),
             self.texture.take().unwrap(),
-        ));
+        // skin: a rough dielectric
+        ).with_material(PbrMaterial::new(
+            PbrParam::Constant(Vec3f(0.6, 0.6, 0.6)),
+            PbrParam::Constant(Vec3f(0.0, 0.0, 0.0)),
+            PbrParam::Constant(Vec3f(0.6, 0.6, 0.6)),
+        )).with_normal_space(self.normal_space));
+    }
+
+    // the normal map of the model: normals.tga in object space, normals_tangent.tga in tangent space
+    fn load_normals(&mut self) {
+        let dir = match self.model_type {
+            ModelType::AFRICAN => "african_head",
+            ModelType::DIABLO => "diablo",
+        };
+        let file = match self.normal_space {
+            NormalSpace::Object => "normals.tga",
+            NormalSpace::Tangent => "normals_tangent.tga",
+        };
+        self.load_binary(format!("./{}/{}", dir, file), Msg::Normals);
     }
 
     fn ready(&self) -> bool {
@@ -186,6 +216,7 @@ impl Component for Model<PerspectiveCamera> {
     fn create(props: Self::Properties, link: yew::ComponentLink<Self>) -> Self {
         Self {
             zbuff: false,
+            samples: 1,
             conf: ShaderConf::new(),
             task: Vec::new(),
             link,
@@ -194,7 +225,9 @@ impl Component for Model<PerspectiveCamera> {
             texture: None,
             wavefront: None,
             normals: None,
+            normal_space: NormalSpace::Object,
             model: None,
+            lights: vec![Light::default()],
             model_type: ModelType::AFRICAN,
             camera: PerspectiveCamera::new(50f32,WIDTH as f32/HEIGHT as f32,0.01f32,1000f32),
             rotation_start: None,
@@ -206,7 +239,7 @@ impl Component for Model<PerspectiveCamera> {
     fn rendered(&mut self, first_render: bool) {
         if first_render {
             self.load_binary("./african_head/texture.tga".to_owned(), Msg::Texture);
-            self.load_binary("./african_head/normals.tga".to_owned(), Msg::Normals);
+            self.load_normals();
             self.load_binary("./african_head/model.obj".to_owned(), Msg::Model);
         }
     }
@@ -230,6 +263,76 @@ impl Component for Model<PerspectiveCamera> {
                 }
                 true
             }
+            Msg::Persp => {
+                self.conf = ShaderConf {
+                    perspective: !self.conf.perspective,
+                    ..self.conf
+                };
+                if self.ready() {
+                    self.render();
+                }
+                true
+            }
+            Msg::Shadow => {
+                self.conf = ShaderConf {
+                    shadows: !self.conf.shadows,
+                    ..self.conf
+                };
+                if self.ready() {
+                    self.render();
+                }
+                true
+            }
+            Msg::Pbr => {
+                self.conf = ShaderConf {
+                    pbr: !self.conf.pbr,
+                    ..self.conf
+                };
+                if self.ready() {
+                    self.render();
+                }
+                true
+            }
+            Msg::Msaa => {
+                // cycle through off, 2x, 4x and 8x
+                self.samples = if self.samples >= 8 { 1 } else { self.samples * 2 };
+                if self.ready() {
+                    self.render();
+                }
+                true
+            }
+            Msg::ReversedZ => {
+                let reversed_z = !self.camera.reversed_z();
+                self.camera.set_reversed_z(reversed_z);
+                if self.ready() {
+                    self.render();
+                }
+                true
+            }
+            Msg::Light => {
+                // cycle through a directional light, a point light above the camera
+                // and a spot light shining from the camera
+                let white = Vec3f(1.0, 1.0, 1.0);
+                self.lights = vec![match self.lights[0] {
+                    Light::Directional { .. } => Light::point(Vec3f(1.0, 1.5, 0.0), white, 2.5),
+                    Light::Point { .. } => Light::spot(Vec3f::zeroed(), Vec3f(0.0, 0.0, -1.0), 0.3, white, 2.5),
+                    Light::Spot { .. } => Light::default(),
+                }];
+                if self.ready() {
+                    self.render();
+                }
+                true
+            }
+            Msg::Ssao => {
+                self.conf = ShaderConf {
+                    ssao: !self.conf.ssao,
+                    ..self.conf
+                };
+                if self.ready() {
+                    self.render();
+                }
+                true
+            }
             Msg::Norm => {
                 self.conf = ShaderConf {
                     normals: !self.conf.normals,
@@ -240,6 +343,20 @@ impl Component for Model<PerspectiveCamera> {
                 }
                 true
             }
+            Msg::NormalSpace => {
+                self.normal_space = match self.normal_space {
+                    NormalSpace::Object => NormalSpace::Tangent,
+                    NormalSpace::Tangent => NormalSpace::Object,
+                };
+                // keep the model and its texture, and load the other normal map
+                if let Some(model) = self.model.take() {
+                    self.wavefront = Some(model.model);
+                    self.texture = Some(model.texture);
+                }
+                self.normals = None;
+                self.load_normals();
+                true
+            }
             Msg::Diff => {
                 self.conf = ShaderConf {
                     diff_light: !self.conf.diff_light,
@@ -292,7 +409,13 @@ impl Component for Model<PerspectiveCamera> {
                 true
             }
             Msg::Texture(v) => {
-                self.texture = Some(Image::from_raw_vec(v));
+                match Image::decode(v) {
+                    Ok(img) => self.texture = Some(img),
+                    Err(e) => {
+                        ConsoleService::error(format!("invalid texture, {}", e).as_str());
+                        return false;
+                    }
+                }
                 if self.ready() {
                     self.prepare();
                     self.render();
@@ -300,7 +423,13 @@ impl Component for Model<PerspectiveCamera> {
                 true
             }
             Msg::Normals(v) => {
-                self.normals = Some(Image::from_raw_vec(v));
+                match Image::decode(v) {
+                    Ok(img) => self.normals = Some(img),
+                    Err(e) => {
+                        ConsoleService::error(format!("invalid normal map, {}", e).as_str());
+                        return false;
+                    }
+                }
                 if self.ready() {
                     self.prepare();
                     self.render();
@@ -308,7 +437,14 @@ impl Component for Model<PerspectiveCamera> {
                 true
             }
             Msg::Model(v) => {
-                self.wavefront = Some(Wavefront::parse_string(String::from_utf8(v).unwrap()));
+                // report a broken file instead of aborting the app
+                match Wavefront::parse_string(&String::from_utf8_lossy(&v)) {
+                    Ok(wf) => self.wavefront = Some(wf),
+                    Err(e) => {
+                        ConsoleService::error(format!("invalid model, {}", e).as_str());
+                        return false;
+                    }
+                }
                 if self.ready() {
                     self.prepare();
                     self.render();
@@ -372,9 +508,7 @@ impl Component for Model<PerspectiveCamera> {
                             self.load_binary("./african_head/texture.tga".to_owned(), |v| {
                                 Msg::Texture(v)
                             });
-                            self.load_binary("./african_head/normals.tga".to_owned(), |v| {
-                                Msg::Normals(v)
-                            });
+                            self.load_normals();
                             self.load_binary("./african_head/model.obj".to_owned(), |v| {
                                 Msg::Model(v)
                             });
@@ -391,9 +525,7 @@ impl Component for Model<PerspectiveCamera> {
                             self.load_binary("./diablo/texture.tga".to_owned(), |v| {
                                 Msg::Texture(v)
                             });
-                            self.load_binary("./diablo/normals.tga".to_owned(), |v| {
-                                Msg::Normals(v)
-                            });
+                            self.load_normals();
                             self.load_binary("./diablo/model.obj".to_owned(), Msg::Model);
                         }
                     }
@@ -530,8 +662,23 @@ impl Component for Model<PerspectiveCamera> {
                             <button class=if self.conf.spec_light { "" } else { "off" } disabled={ self.zbuff } onclick=self.link.callback(move |_| Msg::Spec)>{ "Specular light" }</button>
                             <button class=if self.conf.texture { "" } else { "off" } disabled={ self.zbuff } onclick=self.link.callback(move |_| Msg::Txt)>{ "Texture" }</button>
                             <button class=if self.conf.normals { "" } else { "off" } disabled={ self.zbuff } onclick=self.link.callback(move |_| Msg::Norm)>{ "Normal map" }</button>
+                            <button disabled={ self.zbuff || !self.conf.normals } onclick=self.link.callback(move |_| Msg::NormalSpace)>{ match self.normal_space {
+                                NormalSpace::Object => "Object space normals",
+                                NormalSpace::Tangent => "Tangent space normals",
+                            } }</button>
                             <button class=if self.conf.occlusion { "" } else { "off" } disabled={ self.zbuff } onclick=self.link.callback(move |_| Msg::Occl)>{ "Ambient occlusion" }</button>
+                            <button class=if self.conf.ssao { "" } else { "off" } disabled={ self.zbuff || !self.conf.occlusion } onclick=self.link.callback(move |_| Msg::Ssao)>{ "SSAO" }</button>
+                            <button class=if self.conf.perspective { "" } else { "off" } disabled={ self.zbuff } onclick=self.link.callback(move |_| Msg::Persp)>{ "Perspective correct" }</button>
+                            <button class=if self.conf.shadows { "" } else { "off" } disabled={ self.zbuff } onclick=self.link.callback(move |_| Msg::Shadow)>{ "Shadows" }</button>
+                            <button class=if self.conf.pbr { "" } else { "off" } disabled={ self.zbuff } onclick=self.link.callback(move |_| Msg::Pbr)>{ "PBR" }</button>
+                            <button class=if self.samples != 1 { "" } else { "off" } disabled={ self.zbuff } onclick=self.link.callback(move |_| Msg::Msaa)>{ format!("MSAA {}x", self.samples) }</button>
+                            <button disabled={ self.zbuff } onclick=self.link.callback(move |_| Msg::Light)>{ match self.lights[0] {
+                                Light::Directional { .. } => "Directional light",
+                                Light::Point { .. } => "Point light",
+                                Light::Spot { .. } => "Spot light",
+                            } }</button>
                             <button onclick=self.link.callback(move |_| Msg::Zbuff)>{ "Z Buffer" }</button>
+                            <button class=if self.camera.reversed_z() { "" } else { "off" } onclick=self.link.callback(move |_| Msg::ReversedZ)>{ "Reversed Z" }</button>
                             <div style="height: 100px"></div>
                             <button class=if let ModelType::AFRICAN=self.model_type { "off" } else { "" } onclick=self.link.callback(move |_| Msg::Load(ModelType::AFRICAN))>{ "African head" }</button>
                             <button class=if let ModelType::DIABLO=self.model_type { "off" } else { "" } onclick=self.link.callback(move |_| Msg::Load(ModelType::DIABLO))>{ "Diablo" }</button>